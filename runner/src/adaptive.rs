@@ -0,0 +1,43 @@
+use shader::{luminance, Accumulation};
+
+/// Stops sampling pixels once their noise drops below a threshold, spending the rest of the
+/// samples where the image is still noisy
+#[derive(Clone, Copy)]
+pub struct AdaptiveSampling {
+    /// Standard error of the pixel's luminance relative to its brightness
+    pub noise_threshold: f32,
+
+    /// Samples every pixel takes before its noise is trusted, a few lucky samples can look
+    /// converged
+    pub min_samples: u32,
+}
+
+/// Keeps dark pixels from needing an absurd amount of samples to reach a relative threshold
+const MIN_BRIGHTNESS: f32 = 1e-2;
+
+impl AdaptiveSampling {
+    /// Relative standard error of the pixel's mean luminance
+    fn noise(pixel: Accumulation) -> f32 {
+        let mean = luminance(pixel.average());
+        let standard_error = (pixel.luminance_variance() / pixel.samples as f32).sqrt();
+
+        standard_error / mean.max(MIN_BRIGHTNESS)
+    }
+
+    /// Marks the pixels that are done, returns how many are still sampling
+    pub fn update(&self, accumulation: &mut [Accumulation]) -> usize {
+        for pixel in accumulation.iter_mut() {
+            if pixel.converged == 0
+                && pixel.samples >= self.min_samples.max(2)
+                && Self::noise(*pixel) < self.noise_threshold
+            {
+                pixel.converged = 1;
+            }
+        }
+
+        accumulation
+            .iter()
+            .filter(|pixel| pixel.converged == 0)
+            .count()
+    }
+}
@@ -0,0 +1,261 @@
+use crate::{
+    color::{srgb_to_linear, ColorPipeline},
+    output::{write_image, write_layered_exr, OutputFormat},
+};
+use clap::ValueEnum;
+use shader::{Accumulation, AovAccumulation, NO_OBJECT};
+use std::{
+    error::Error,
+    path::{Path, PathBuf},
+};
+use vek::{Vec2, Vec3};
+
+/// Arbitrary output variables, what the camera rays found at their first hit. Pixels where every
+/// sample missed are zero
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Aov {
+    /// Distance from the camera
+    Depth,
+
+    /// World space position
+    Position,
+
+    /// Shading normal, facing the camera
+    Normal,
+
+    /// Surface color, with textures applied
+    Albedo,
+
+    /// Index of the sphere, or the amount of spheres plus the index of the triangle, in the order
+    /// the scene lists them. Taken from the first sample
+    ObjectId,
+
+    /// Identical materials share an id, numbered in the order they show up in the scene. Taken
+    /// from the first sample
+    MaterialId,
+
+    /// Samples taken per pixel, a heatmap in low dynamic range formats
+    Samples,
+}
+
+impl Aov {
+    fn name(self) -> &'static str {
+        match self {
+            Aov::Depth => "depth",
+            Aov::Position => "position",
+            Aov::Normal => "normal",
+            Aov::Albedo => "albedo",
+            Aov::ObjectId => "object_id",
+            Aov::MaterialId => "material_id",
+            Aov::Samples => "samples",
+        }
+    }
+
+    /// Names of the channels in an EXR layer, single channel passes only use the first value
+    fn channel_names(self) -> &'static [&'static str] {
+        match self {
+            Aov::Depth => &["Z"],
+            Aov::Position | Aov::Normal => &["X", "Y", "Z"],
+            Aov::Albedo => &["R", "G", "B"],
+            Aov::ObjectId | Aov::MaterialId => &["id"],
+            Aov::Samples => &["count"],
+        }
+    }
+
+    /// The pass as stored, single channel passes repeat their value
+    fn values(self, accumulation: &[Accumulation], aovs: &[AovAccumulation]) -> Vec<Vec3<f32>> {
+        let id = |id| {
+            if id == NO_OBJECT {
+                Vec3::broadcast(-1.)
+            } else {
+                Vec3::broadcast(id as f32)
+            }
+        };
+
+        accumulation
+            .iter()
+            .zip(aovs)
+            .map(|(pixel, aovs)| match self {
+                Aov::Depth => Vec3::broadcast(aovs.average_depth()),
+                Aov::Position => aovs.average_position(),
+                Aov::Normal => aovs.average_normal(),
+                Aov::Albedo => aovs.average_albedo(),
+                Aov::ObjectId => id(aovs.object_id),
+                Aov::MaterialId => id(aovs.material_id),
+                Aov::Samples => Vec3::broadcast(pixel.samples as f32),
+            })
+            .collect()
+    }
+
+    /// Maps the values to colors from zero to one that show the pass as an image
+    fn visualize(self, values: Vec<Vec3<f32>>) -> Vec<Vec3<f32>> {
+        match self {
+            // The nearest hit is white, further ones fade to black like the background
+            Aov::Depth => {
+                let nearest = values
+                    .iter()
+                    .map(|depth| depth.x)
+                    .filter(|&depth| depth > 0.)
+                    .fold(f32::INFINITY, f32::min);
+
+                values
+                    .into_iter()
+                    .map(|depth| {
+                        if depth.x > 0. {
+                            Vec3::broadcast(nearest / depth.x)
+                        } else {
+                            Vec3::zero()
+                        }
+                    })
+                    .collect()
+            }
+
+            // Stretched over the bounds of what was hit
+            Aov::Position => {
+                let hits = || values.iter().copied().filter(|&p| p != Vec3::zero());
+                let min = hits().reduce(Vec3::partial_min).unwrap_or_default();
+                let max = hits().reduce(Vec3::partial_max).unwrap_or_default();
+                let size = (max - min).map(|c| c.max(f32::EPSILON));
+
+                values
+                    .iter()
+                    .map(|&p| {
+                        if p == Vec3::zero() {
+                            p
+                        } else {
+                            (p - min) / size
+                        }
+                    })
+                    .collect()
+            }
+
+            Aov::Normal => values
+                .into_iter()
+                .map(|normal| {
+                    if normal == Vec3::zero() {
+                        normal
+                    } else {
+                        normal * 0.5 + 0.5
+                    }
+                })
+                .collect(),
+
+            Aov::Albedo => values,
+
+            // A random looking color per object or material
+            Aov::ObjectId | Aov::MaterialId => values
+                .into_iter()
+                .map(|id| {
+                    if id.x < 0. {
+                        return Vec3::zero();
+                    }
+
+                    let hash = (id.x as u32).wrapping_add(1).wrapping_mul(0x9e3779b1);
+                    Vec3::new(hash >> 24, (hash >> 16) & 0xff, (hash >> 8) & 0xff).as_::<f32>()
+                        / 255.
+                })
+                .collect(),
+
+            // Heatmap from blue for the fewest samples to red for the most
+            Aov::Samples => {
+                let most = values.iter().map(|samples| samples.x).fold(1., f32::max);
+
+                values
+                    .into_iter()
+                    .map(|samples| heat(samples.x / most))
+                    .collect()
+            }
+        }
+    }
+}
+
+/// Blue through cyan, green and yellow to red as the value goes from zero to one
+fn heat(value: f32) -> Vec3<f32> {
+    const COLORS: [Vec3<f32>; 5] = [
+        Vec3::new(0., 0., 1.),
+        Vec3::new(0., 1., 1.),
+        Vec3::new(0., 1., 0.),
+        Vec3::new(1., 1., 0.),
+        Vec3::new(1., 0., 0.),
+    ];
+
+    let position = value.clamp(0., 1.) * (COLORS.len() - 1) as f32;
+    let index = (position as usize).min(COLORS.len() - 2);
+
+    Vec3::lerp(COLORS[index], COLORS[index + 1], position - index as f32)
+}
+
+/// Where a pass is written next to the image, `image.png` becomes `image.depth.png`
+fn aov_path(output: &Path, aov: Aov) -> PathBuf {
+    let mut name = output.file_stem().unwrap_or_default().to_os_string();
+    name.push(".");
+    name.push(aov.name());
+
+    if let Some(extension) = output.extension() {
+        name.push(".");
+        name.push(extension);
+    }
+
+    output.with_file_name(name)
+}
+
+/// Writes the image along with the passes, as layers of the same file for EXR and as images next
+/// to it for other formats. Low dynamic range formats get a visualization of each pass, the others
+/// keep the values
+#[allow(clippy::too_many_arguments)]
+pub fn write_image_with_aovs(
+    output: &Path,
+    format: OutputFormat,
+    screen_size: Vec2<u32>,
+    pixels: &[Vec3<f32>],
+    accumulation: &[Accumulation],
+    aov_accumulation: &[AovAccumulation],
+    aovs: &[Aov],
+    pipeline: &ColorPipeline,
+    metadata: &[(&str, String)],
+) -> Result<(), Box<dyn Error>> {
+    if format == OutputFormat::Exr {
+        let channels: Vec<_> = aovs
+            .iter()
+            .flat_map(|&aov| {
+                let values = aov.values(accumulation, aov_accumulation);
+
+                aov.channel_names()
+                    .iter()
+                    .enumerate()
+                    .map(move |(i, name)| {
+                        let channel: Vec<f32> = values.iter().map(|value| value[i]).collect();
+                        (format!("{}.{name}", aov.name()), channel)
+                    })
+            })
+            .collect();
+        return write_layered_exr(output, screen_size, pixels, channels, metadata);
+    }
+
+    write_image(output, format, screen_size, pixels, pipeline, metadata)?;
+
+    for &aov in aovs {
+        let values = aov.values(accumulation, aov_accumulation);
+
+        // The pipeline sRGB encodes, the visualization is decoded first so it comes out as is
+        let values = if format.is_low_dynamic_range() {
+            aov.visualize(values)
+                .into_iter()
+                .map(|value| value.map(|c| srgb_to_linear(c.clamp(0., 1.))))
+                .collect()
+        } else {
+            values
+        };
+
+        write_image(
+            aov_path(output, aov),
+            format,
+            screen_size,
+            &values,
+            &ColorPipeline::default(),
+            metadata,
+        )?;
+    }
+
+    Ok(())
+}
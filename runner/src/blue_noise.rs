@@ -0,0 +1,132 @@
+use rand::{rngs::StdRng, seq::index, SeedableRng};
+use shader::BLUE_NOISE_SIZE;
+use std::sync::OnceLock;
+use vek::Vec2;
+
+/// Spread of the gaussian that measures how crowded the pixels around a point are, the value from
+/// Ulichney's paper
+const SIGMA: f32 = 1.5;
+
+/// How far the gaussian reaches, it's negligible further than three standard deviations out
+const RADIUS: i32 = 5;
+
+/// Part of the pixels set in the pattern the ranks are built from
+const INITIAL_DENSITY: f32 = 0.1;
+
+/// Binary pattern on the tile, along with how crowded every pixel's surroundings are
+#[derive(Clone)]
+struct Pattern {
+    ones: Vec<bool>,
+    energy: Vec<f32>,
+
+    /// Gaussian of the offsets up to `RADIUS` away, row by row
+    falloff: Vec<f32>,
+}
+
+impl Pattern {
+    fn new() -> Self {
+        let pixels = BLUE_NOISE_SIZE.pow(2) as usize;
+        let falloff = (-RADIUS..=RADIUS)
+            .flat_map(|y| (-RADIUS..=RADIUS).map(move |x| Vec2::new(x, y).as_::<f32>()))
+            .map(|offset| (-offset.magnitude_squared() / (2. * SIGMA * SIGMA)).exp())
+            .collect();
+
+        Self {
+            ones: vec![false; pixels],
+            energy: vec![0.; pixels],
+            falloff,
+        }
+    }
+
+    /// Sets or unsets a pixel, the energy wraps around the tile so it repeats without seams
+    fn toggle(&mut self, pixel: usize) {
+        let size = BLUE_NOISE_SIZE as i32;
+        let sign = if self.ones[pixel] { -1. } else { 1. };
+        self.ones[pixel] = !self.ones[pixel];
+
+        let position = Vec2::new(pixel as i32 % size, pixel as i32 / size);
+        let offsets =
+            (-RADIUS..=RADIUS).flat_map(|y| (-RADIUS..=RADIUS).map(move |x| Vec2::new(x, y)));
+        for (offset, falloff) in offsets.zip(&self.falloff) {
+            let neighbour = (position + offset).map(|x| x.rem_euclid(size));
+            self.energy[(neighbour.y * size + neighbour.x) as usize] += sign * falloff;
+        }
+    }
+
+    /// The set pixel with the most set pixels around it
+    fn tightest_cluster(&self) -> usize {
+        self.extreme(true, |a, b| a > b)
+    }
+
+    /// The unset pixel with the fewest set pixels around it
+    fn largest_void(&self) -> usize {
+        self.extreme(false, |a, b| a < b)
+    }
+
+    fn extreme(&self, set: bool, better: impl Fn(f32, f32) -> bool) -> usize {
+        let mut candidates = (0..self.ones.len()).filter(|&i| self.ones[i] == set);
+        let first = candidates.next().expect("Pattern is full or empty");
+
+        candidates.fold(first, |best, i| {
+            if better(self.energy[i], self.energy[best]) {
+                i
+            } else {
+                best
+            }
+        })
+    }
+}
+
+/// Blue noise tile of `BLUE_NOISE_SIZE` squared values from zero to one, each used once and
+/// spread so that close pixels get values far apart. Built on the first call and shared after
+pub fn blue_noise_mask() -> &'static [f32] {
+    static MASK: OnceLock<Vec<f32>> = OnceLock::new();
+    MASK.get_or_init(void_and_cluster)
+}
+
+/// "The void-and-cluster method for dither array generation", Ulichney 1993
+fn void_and_cluster() -> Vec<f32> {
+    let pixels = BLUE_NOISE_SIZE.pow(2) as usize;
+    let initial_ones = (pixels as f32 * INITIAL_DENSITY) as usize;
+
+    let mut pattern = Pattern::new();
+    let mut rng = StdRng::seed_from_u64(0);
+    for pixel in index::sample(&mut rng, pixels, initial_ones) {
+        pattern.toggle(pixel);
+    }
+
+    // Moves pixels out of clusters into voids until they're spread evenly
+    loop {
+        let cluster = pattern.tightest_cluster();
+        pattern.toggle(cluster);
+
+        let void = pattern.largest_void();
+        pattern.toggle(void);
+
+        if void == cluster {
+            break;
+        }
+    }
+
+    let mut ranks = vec![0; pixels];
+
+    // The pixels of the pattern rank below its size, taking them out from the most crowded
+    let mut shrinking = pattern.clone();
+    for rank in (0..initial_ones).rev() {
+        let cluster = shrinking.tightest_cluster();
+        shrinking.toggle(cluster);
+        ranks[cluster] = rank;
+    }
+
+    // The others rank above it, filling in the emptiest places first
+    for rank in initial_ones..pixels {
+        let void = pattern.largest_void();
+        pattern.toggle(void);
+        ranks[void] = rank;
+    }
+
+    ranks
+        .into_iter()
+        .map(|rank| (rank as f32 + 0.5) / pixels as f32)
+        .collect()
+}
@@ -0,0 +1,266 @@
+use shader::{Aabb, Bounded, BvhNode, BVH_MAX_DEPTH};
+use vek::Vec3;
+
+/// Amount of buckets centroids are sorted into when evaluating split candidates
+const BIN_COUNT: usize = 12;
+
+/// Leaves are only forced to split above this size, below it the heuristic decides
+const MAX_LEAF_SIZE: usize = 4;
+
+/// Cost of traversing an interior node, relative to intersecting one primitive
+const TRAVERSAL_COST: f32 = 1.;
+
+#[derive(Clone, Copy)]
+struct Split {
+    axis: usize,
+    /// Centroid range the bins were laid out over
+    min: f32,
+    extent: f32,
+    /// Primitives in bins before this one go to the left child
+    plane: usize,
+    cost: f32,
+}
+
+struct BvhBuilder {
+    bounds: Vec<Aabb>,
+    centroids: Vec<Vec3<f32>>,
+    indices: Vec<usize>,
+    nodes: Vec<BvhNode>,
+}
+
+impl BvhBuilder {
+    fn bounds_of(&self, first: usize, amount: usize) -> Aabb {
+        self.indices[first..first + amount]
+            .iter()
+            .map(|&i| self.bounds[i])
+            .reduce(Aabb::combine)
+            .expect("Empty bvh node")
+    }
+
+    fn centroid_bounds_of(&self, first: usize, amount: usize) -> Aabb {
+        self.indices[first..first + amount]
+            .iter()
+            .map(|&i| Aabb::from_extremes(self.centroids[i], self.centroids[i]))
+            .reduce(Aabb::combine)
+            .expect("Empty bvh node")
+    }
+
+    /// Finds the cheapest split according to the surface area heuristic, binning centroids along
+    /// every axis
+    fn find_split(&self, first: usize, amount: usize, bounding_box: Aabb) -> Option<Split> {
+        let centroid_bounds = self.centroid_bounds_of(first, amount);
+        let parent_area = bounding_box.surface_area();
+
+        let mut best_split: Option<Split> = None;
+
+        for axis in 0..3 {
+            let axis_range = centroid_bounds.axes[axis];
+            let extent = axis_range.size();
+
+            if extent <= 0. {
+                continue;
+            }
+
+            let mut bin_bounds: [Option<Aabb>; BIN_COUNT] = [None; BIN_COUNT];
+            let mut bin_counts = [0usize; BIN_COUNT];
+
+            for &i in &self.indices[first..first + amount] {
+                let bin = bin_index(self.centroids[i][axis], axis_range.min, extent);
+
+                bin_counts[bin] += 1;
+                bin_bounds[bin] = Some(match bin_bounds[bin] {
+                    Some(bounds) => Aabb::combine(bounds, self.bounds[i]),
+                    None => self.bounds[i],
+                });
+            }
+
+            // Sweep from the right to know the cost of everything right of each plane
+            let mut right_areas = [0.; BIN_COUNT];
+            let mut right_counts = [0usize; BIN_COUNT];
+            let mut right_bounds: Option<Aabb> = None;
+            let mut right_count = 0;
+
+            for bin in (1..BIN_COUNT).rev() {
+                right_count += bin_counts[bin];
+                right_bounds = combine_optional(right_bounds, bin_bounds[bin]);
+
+                right_counts[bin] = right_count;
+                right_areas[bin] = right_bounds.map_or(0., Aabb::surface_area);
+            }
+
+            let mut left_bounds: Option<Aabb> = None;
+            let mut left_count = 0;
+
+            for plane in 1..BIN_COUNT {
+                left_count += bin_counts[plane - 1];
+                left_bounds = combine_optional(left_bounds, bin_bounds[plane - 1]);
+
+                if left_count == 0 || right_counts[plane] == 0 {
+                    continue;
+                }
+
+                let left_area = left_bounds.map_or(0., Aabb::surface_area);
+                let cost = TRAVERSAL_COST
+                    + (left_count as f32 * left_area
+                        + right_counts[plane] as f32 * right_areas[plane])
+                        / parent_area;
+
+                if best_split.map_or(true, |split| cost < split.cost) {
+                    best_split = Some(Split {
+                        axis,
+                        min: axis_range.min,
+                        extent,
+                        plane,
+                        cost,
+                    });
+                }
+            }
+        }
+
+        best_split
+    }
+
+    /// Splits at the median centroid along the longest axis, used when the heuristic can't
+    /// separate the primitives but the leaf would be too large
+    fn median_split(&mut self, first: usize, amount: usize) -> usize {
+        let centroid_bounds = self.centroid_bounds_of(first, amount);
+        let size = centroid_bounds.axes.map(|axis| axis.size());
+
+        let axis = if size.x >= size.y && size.x >= size.z {
+            0
+        } else if size.y >= size.z {
+            1
+        } else {
+            2
+        };
+
+        let centroids = &self.centroids;
+        self.indices[first..first + amount]
+            .sort_unstable_by(|&a, &b| centroids[a][axis].total_cmp(&centroids[b][axis]));
+
+        amount / 2
+    }
+
+    /// Moves every primitive with a centroid left of the split to the front, returns how many
+    fn partition(&mut self, first: usize, amount: usize, split: Split) -> usize {
+        let mut left = first;
+        let mut right = first + amount;
+
+        while left < right {
+            let centroid = self.centroids[self.indices[left]][split.axis];
+
+            if bin_index(centroid, split.min, split.extent) < split.plane {
+                left += 1;
+            } else {
+                right -= 1;
+                self.indices.swap(left, right);
+            }
+        }
+
+        left - first
+    }
+
+    fn build(&mut self, node_index: usize, first: usize, amount: usize, depth: usize) {
+        let bounding_box = self.bounds_of(first, amount);
+        let leaf = BvhNode::leaf(bounding_box, first as u32, amount as u32);
+
+        if amount == 1 || depth >= BVH_MAX_DEPTH {
+            self.nodes[node_index] = leaf;
+            return;
+        }
+
+        let leaf_cost = amount as f32;
+        let left_amount = match self.find_split(first, amount, bounding_box) {
+            Some(split) if split.cost < leaf_cost || amount > MAX_LEAF_SIZE => {
+                self.partition(first, amount, split)
+            }
+            None if amount > MAX_LEAF_SIZE => self.median_split(first, amount),
+            _ => {
+                self.nodes[node_index] = leaf;
+                return;
+            }
+        };
+
+        let left_index = self.nodes.len();
+        self.nodes.push(leaf);
+        self.nodes.push(leaf);
+        self.nodes[node_index] = BvhNode::interior(bounding_box, left_index as u32);
+
+        self.build(left_index, first, left_amount, depth + 1);
+        self.build(
+            left_index + 1,
+            first + left_amount,
+            amount - left_amount,
+            depth + 1,
+        );
+    }
+}
+
+fn bin_index(value: f32, min: f32, extent: f32) -> usize {
+    let bin = ((value - min) / extent * BIN_COUNT as f32) as usize;
+
+    bin.min(BIN_COUNT - 1)
+}
+
+fn combine_optional(a: Option<Aabb>, b: Option<Aabb>) -> Option<Aabb> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(Aabb::combine(a, b)),
+        (a, None) => a,
+        (None, b) => b,
+    }
+}
+
+/// Builds a flattened bvh over the primitives using the surface area heuristic. The primitives are
+/// reordered so that every leaf references a contiguous range of them
+pub fn build_bvh<T: Bounded + Copy>(primitives: &mut [T]) -> Vec<BvhNode> {
+    build_bvh_by(primitives, T::get_aabb)
+}
+
+/// Same as [`build_bvh`], for primitives that need outside data to compute their bounds
+pub fn build_bvh_by<T: Copy>(primitives: &mut [T], get_aabb: impl Fn(T) -> Aabb) -> Vec<BvhNode> {
+    if primitives.is_empty() {
+        return Vec::new();
+    }
+
+    let bounds: Vec<Aabb> = primitives
+        .iter()
+        .map(|&primitive| get_aabb(primitive))
+        .collect();
+    let centroids = bounds.iter().map(|bounds| bounds.centroid()).collect();
+
+    let mut builder = BvhBuilder {
+        bounds,
+        centroids,
+        indices: (0..primitives.len()).collect(),
+        nodes: Vec::with_capacity(2 * primitives.len()),
+    };
+
+    builder.nodes.push(BvhNode::leaf(builder.bounds[0], 0, 0));
+    builder.build(0, 0, primitives.len(), 0);
+
+    let reordered: Vec<T> = builder.indices.iter().map(|&i| primitives[i]).collect();
+    primitives.copy_from_slice(&reordered);
+
+    builder.nodes
+}
+
+/// Amount of levels below the root, for reporting
+pub fn bvh_depth(nodes: &[BvhNode]) -> usize {
+    fn depth_of(nodes: &[BvhNode], index: usize) -> usize {
+        let node = nodes[index];
+
+        if node.is_leaf() {
+            0
+        } else {
+            let left = node.left_or_first as usize;
+
+            1 + depth_of(nodes, left).max(depth_of(nodes, left + 1))
+        }
+    }
+
+    if nodes.is_empty() {
+        0
+    } else {
+        depth_of(nodes, 0)
+    }
+}
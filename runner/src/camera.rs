@@ -0,0 +1,82 @@
+use serde::{Deserialize, Serialize};
+use shader::Viewport;
+use vek::{num_traits::Float, Vec2, Vec3};
+
+/// Angles are stored in radians but written to scene files in degrees
+pub mod degrees {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(radians: &f32, serializer: S) -> Result<S::Ok, S::Error> {
+        radians.to_degrees().serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f32, D::Error> {
+        f32::deserialize(deserializer).map(f32::to_radians)
+    }
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct Camera {
+    pub position: Vec3<f32>,
+    pub target: Vec3<f32>,
+    pub up: Vec3<f32>,
+
+    #[serde(with = "degrees")]
+    pub vertical_fov: f32,
+    #[serde(with = "degrees")]
+    pub defocus_angle: f32,
+    pub focus_distance: f32,
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Self {
+            position: Vec3::new(13., 2., 3.),
+            target: Vec3::new(0., 0., 0.),
+            up: Vec3::new(0., 1., 0.),
+
+            vertical_fov: (20_f32).to_radians(),
+            defocus_angle: (0.6_f32).to_radians(),
+            focus_distance: 10.,
+        }
+    }
+}
+
+pub fn calculate_viewport(camera: Camera, screen_size: Vec2<u32>) -> Viewport {
+    let aspect_ratio = (screen_size.x as f32) / (screen_size.y as f32);
+
+    let h = Float::tan(camera.vertical_fov / 2.);
+    let height = 2.0 * h * camera.focus_distance;
+    let width = height * aspect_ratio;
+
+    let w = (camera.position - camera.target).normalized();
+    let u = Vec3::cross(camera.up, w).normalized();
+    let v = Vec3::cross(w, u);
+
+    let horizontal = width * u;
+    let vertical = -height * v;
+
+    let horizontal_pixel_delta = horizontal / (screen_size.x as f32);
+    let vertical_pixel_delta = vertical / (screen_size.y as f32);
+
+    let upper_left_corner =
+        camera.position - (camera.focus_distance * w) - horizontal / 2. - vertical / 2.;
+
+    let upper_left_pixel_position =
+        upper_left_corner + horizontal_pixel_delta / 2. + vertical_pixel_delta / 2.;
+
+    let defocus_radius = camera.focus_distance * Float::tan(camera.defocus_angle / 2.);
+    let horizontal_defocus_disk = u * defocus_radius;
+    let vertical_defocus_disk = v * defocus_radius;
+
+    Viewport {
+        origin: camera.position,
+        upper_left_pixel_position,
+
+        horizontal_pixel_delta,
+        vertical_pixel_delta,
+
+        horizontal_defocus_disk,
+        vertical_defocus_disk,
+    }
+}
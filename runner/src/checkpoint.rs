@@ -0,0 +1,163 @@
+use crate::render::RenderData;
+use bytemuck::Pod;
+use shader::{Accumulation, AovAccumulation, RaytraceSettings};
+use std::{
+    error::Error,
+    fs,
+    io::{self, Read},
+    mem::size_of,
+    path::Path,
+};
+use vek::Vec2;
+
+const MAGIC: &[u8; 4] = b"RTCK";
+const VERSION: u32 = 1;
+
+/// Everything needed to pick a render back up where it stopped
+pub struct Checkpoint {
+    /// [`scene_hash`] of the scene being rendered
+    pub scene_hash: u64,
+
+    /// Master seed the sample seeds are derived from
+    pub seed: u64,
+
+    /// Samples taken so far, the index of the next sample seed
+    pub samples: u32,
+
+    pub screen_size: Vec2<u32>,
+    pub accumulation: Vec<Accumulation>,
+
+    /// First hits of every pixel, empty when the render didn't write them
+    pub aovs: Vec<AovAccumulation>,
+}
+
+/// FNV-1a, stable across platforms and Rust versions unlike the standard hasher
+fn hash_bytes(hash: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(hash, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+fn hash_slice<T: Pod>(hash: u64, values: &[T]) -> u64 {
+    let hash = hash_bytes(hash, &(values.len() as u64).to_le_bytes());
+    hash_bytes(hash, bytemuck::cast_slice(values))
+}
+
+/// Identifies a scene as the shader sees it. The amount of samples and whether the first hits are
+/// written are left out, so a render can be continued with more samples or other passes than it
+/// started with
+pub fn scene_hash(data: &RenderData) -> u64 {
+    let raytrace_settings = RaytraceSettings {
+        amount_of_samples: 0,
+        write_aovs: 0,
+        ..data.raytrace_settings
+    };
+
+    let mut hash = 0xcbf29ce484222325;
+    hash = hash_slice(hash, &[raytrace_settings]);
+    hash = hash_slice(hash, &data.spheres);
+    hash = hash_slice(hash, &data.sphere_bvh_nodes);
+    hash = hash_slice(hash, &data.vertices);
+    hash = hash_slice(hash, &data.triangles);
+    hash = hash_slice(hash, &data.triangle_bvh_nodes);
+    hash = hash_slice(hash, &data.lights);
+    hash = hash_slice(hash, &data.textures);
+    hash = hash_slice(hash, &data.texels);
+    hash_slice(hash, &data.environment_distribution)
+}
+
+fn read_u32(reader: &mut impl Read) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64(reader: &mut impl Read) -> io::Result<u64> {
+    let mut bytes = [0; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn read_pixels<T: Pod>(bytes: &[u8]) -> Vec<T> {
+    bytes
+        .chunks_exact(size_of::<T>())
+        .map(bytemuck::pod_read_unaligned)
+        .collect()
+}
+
+impl Checkpoint {
+    /// Writes next to the path first and then moves it in place, so a crash while saving leaves
+    /// the previous checkpoint intact
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let path = path.as_ref();
+
+        let mut output = MAGIC.to_vec();
+        output.extend(VERSION.to_le_bytes());
+        output.extend(self.scene_hash.to_le_bytes());
+        output.extend(self.seed.to_le_bytes());
+        output.extend(self.samples.to_le_bytes());
+        output.extend(self.screen_size.x.to_le_bytes());
+        output.extend(self.screen_size.y.to_le_bytes());
+        output.extend((!self.aovs.is_empty() as u32).to_le_bytes());
+        output.extend(bytemuck::cast_slice(&self.accumulation));
+        output.extend(bytemuck::cast_slice(&self.aovs));
+
+        // Appended to the whole name, checkpoints that only differ in extension don't collide
+        let mut temporary_path = path.as_os_str().to_os_string();
+        temporary_path.push(".tmp");
+
+        fs::write(&temporary_path, output)?;
+        fs::rename(temporary_path, path)
+    }
+
+    /// Loads a checkpoint, failing when it was made for a different scene
+    pub fn load(path: impl AsRef<Path>, scene_hash: u64) -> Result<Self, Box<dyn Error>> {
+        let contents = fs::read(path)?;
+        let mut reader = contents.as_slice();
+
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err("Not a checkpoint".into());
+        }
+
+        let version = read_u32(&mut reader)?;
+        if version != VERSION {
+            return Err(format!("Unsupported checkpoint version {version}").into());
+        }
+
+        let checkpoint_scene_hash = read_u64(&mut reader)?;
+        if checkpoint_scene_hash != scene_hash {
+            return Err("Checkpoint was made for a different scene or settings".into());
+        }
+
+        let seed = read_u64(&mut reader)?;
+        let samples = read_u32(&mut reader)?;
+        let screen_size = Vec2::new(read_u32(&mut reader)?, read_u32(&mut reader)?);
+        let has_aovs = read_u32(&mut reader)? != 0;
+
+        let pixels = screen_size.product() as usize;
+        let pixel_size = if has_aovs {
+            size_of::<Accumulation>() + size_of::<AovAccumulation>()
+        } else {
+            size_of::<Accumulation>()
+        };
+        if reader.len() != pixels * pixel_size {
+            return Err("Checkpoint is truncated".into());
+        }
+
+        // The file may not be aligned for the pixels, so they're copied out
+        let (accumulation, aovs) = reader.split_at(pixels * size_of::<Accumulation>());
+        let accumulation = read_pixels(accumulation);
+        let aovs = read_pixels(aovs);
+
+        Ok(Self {
+            scene_hash,
+            seed,
+            samples,
+            screen_size,
+            accumulation,
+            aovs,
+        })
+    }
+}
@@ -0,0 +1,297 @@
+use crate::{
+    adaptive::AdaptiveSampling,
+    aov::Aov,
+    color::{ColorPipeline, Exposure, ToneMapping},
+    denoise::Denoiser,
+    lut::Lut,
+    output::OutputFormat,
+    render::{Backend, PassSettings},
+    scene::RenderSettings,
+    scene_file::{BackgroundDescription, SamplerDescription},
+};
+use clap::{Args, Parser, Subcommand};
+use std::{error::Error, path::PathBuf};
+use vek::Vec2;
+
+#[derive(Parser)]
+#[command(about = "Compute shader raytracer")]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Render a scene file, or an `.obj`, `.gltf` or `.glb` file, to an image
+    Render(RenderArgs),
+
+    /// Print statistics about a scene without rendering it
+    Info { scene: PathBuf },
+
+    /// Time rendering the random spheres scene
+    Bench {
+        #[command(flatten)]
+        options: RenderOptions,
+    },
+
+    /// Write a scene file, converting an existing scene or generating the random spheres scene
+    Export {
+        /// `.ron`, `.json` or `.toml` file to write
+        output: PathBuf,
+
+        /// Scene to convert, the random spheres scene is generated when left out
+        #[arg(long)]
+        scene: Option<PathBuf>,
+
+        /// Seed for generating the random spheres scene, random when left out
+        #[arg(long)]
+        seed: Option<u64>,
+    },
+}
+
+#[derive(Args)]
+pub struct RenderArgs {
+    pub scene: PathBuf,
+
+    #[command(flatten)]
+    pub options: RenderOptions,
+
+    #[command(flatten)]
+    pub color: ColorOptions,
+
+    #[command(flatten)]
+    pub denoise: DenoiseOptions,
+
+    /// Where to write the image
+    #[arg(short, long, default_value = "image.png")]
+    pub output: PathBuf,
+
+    /// Picked from the output extension when left out
+    #[arg(short, long, value_enum)]
+    pub format: Option<OutputFormat>,
+
+    /// Also write the metadata to a text file next to the image, `image.pfm.txt`, for PFM which
+    /// has no room for it
+    #[arg(long)]
+    pub metadata_file: bool,
+
+    /// Passes to write along with the image, as layers of an EXR or as images next to it
+    #[arg(long, value_enum, value_delimiter = ',')]
+    pub aov: Vec<Aov>,
+
+    /// Write the image after every pass, to watch the render as it goes
+    #[arg(long)]
+    pub preview: bool,
+
+    /// Stop after the pass that runs past this many seconds, the image has fewer samples
+    #[arg(long)]
+    pub time_limit: Option<f32>,
+
+    /// Keep the progress in this file, written periodically and when the render stops, so it can
+    /// be resumed
+    #[arg(long)]
+    pub checkpoint: Option<PathBuf>,
+
+    /// Seconds between checkpoints
+    #[arg(long, default_value_t = 300.)]
+    pub checkpoint_interval: f32,
+
+    /// Continue from the checkpoint, the scene and settings have to match apart from the samples
+    #[arg(long, requires = "checkpoint")]
+    pub resume: bool,
+}
+
+/// Overrides for the settings stored in the scene
+#[derive(Args)]
+pub struct RenderOptions {
+    /// Image size, as WIDTHxHEIGHT
+    #[arg(short, long, value_parser = parse_resolution)]
+    pub resolution: Option<Vec2<u32>>,
+
+    /// Samples per pixel
+    #[arg(short, long)]
+    pub samples: Option<u32>,
+
+    /// Maximum amount of bounces per path
+    #[arg(short, long)]
+    pub depth: Option<u32>,
+
+    /// Bounces before Russian roulette may end a path, off unless this or the scene sets it
+    #[arg(long)]
+    pub russian_roulette_depth: Option<u32>,
+
+    /// Master seed for the samples and the generated scene, overrides the scene's own seed. Random
+    /// when neither is set, the seed used gets printed and stored in the image
+    #[arg(long)]
+    pub seed: Option<u64>,
+
+    /// Turn off the sky or environment map, only emissive materials light the scene
+    #[arg(long)]
+    pub no_background: bool,
+
+    /// How the random numbers of the samples are picked, overrides the scene's sampler
+    #[arg(long, value_enum)]
+    pub sampler: Option<SamplerDescription>,
+
+    #[arg(short, long, value_enum, default_value_t)]
+    pub backend: Backend,
+
+    /// Largest part of the image rendered by one gpu dispatch, as WIDTHxHEIGHT
+    #[arg(long, value_parser = parse_resolution, default_value = "1024x1024")]
+    pub tile_size: Vec2<u32>,
+
+    /// Samples added to every pixel per pass over the image, doesn't change the final image
+    /// unless sampling adaptively
+    #[arg(long, default_value_t = 16)]
+    pub samples_per_pass: u32,
+
+    /// Stop sampling pixels once the standard error of their brightness drops below this fraction
+    /// of it, checked after every pass. The samples setting becomes the most a pixel gets
+    #[arg(long)]
+    pub noise_threshold: Option<f32>,
+
+    /// Samples every pixel takes before it may stop early
+    #[arg(long, default_value_t = 16, requires = "noise_threshold")]
+    pub min_samples: u32,
+}
+
+impl RenderOptions {
+    pub fn pass_settings(&self) -> PassSettings {
+        PassSettings {
+            tile_size: self.tile_size,
+            samples_per_pass: self.samples_per_pass,
+            adaptive: self
+                .noise_threshold
+                .map(|noise_threshold| AdaptiveSampling {
+                    noise_threshold,
+                    min_samples: self.min_samples,
+                }),
+        }
+    }
+
+    pub fn apply(&self, settings: &mut RenderSettings) {
+        if let Some(resolution) = self.resolution {
+            settings.screen_size = resolution;
+        }
+
+        if let Some(samples) = self.samples {
+            settings.amount_of_samples = samples;
+        }
+
+        if let Some(depth) = self.depth {
+            settings.max_depth = depth;
+        }
+
+        if let Some(depth) = self.russian_roulette_depth {
+            settings.russian_roulette_depth = Some(depth);
+        }
+
+        if let Some(seed) = self.seed {
+            settings.seed = Some(seed);
+        }
+
+        if self.no_background {
+            settings.background = BackgroundDescription::Black;
+        }
+
+        if let Some(sampler) = self.sampler {
+            settings.sampler = sampler;
+        }
+    }
+}
+
+/// Post-processing for 8 and 16-bit output, floating point formats are written as rendered
+#[derive(Args)]
+pub struct ColorOptions {
+    /// Exposure adjustment in stops
+    #[arg(long, default_value_t = 0., allow_hyphen_values = true)]
+    pub exposure: f32,
+
+    /// Expose for the log-average luminance, `--exposure` adjusts on top of it
+    #[arg(long)]
+    pub auto_exposure: bool,
+
+    #[arg(long, value_enum, default_value_t)]
+    pub tone_mapping: ToneMapping,
+
+    /// Dither before quantizing, hiding banding in smooth gradients
+    #[arg(long)]
+    pub dither: bool,
+
+    /// `.cube` 3D lookup table, applied after the sRGB encoding
+    #[arg(long)]
+    pub lut: Option<PathBuf>,
+}
+
+impl ColorOptions {
+    pub fn pipeline(&self) -> Result<ColorPipeline, Box<dyn Error>> {
+        let exposure = if self.auto_exposure {
+            Exposure::Auto(self.exposure)
+        } else {
+            Exposure::Manual(self.exposure)
+        };
+
+        let lut = self.lut.as_ref().map(Lut::load).transpose()?;
+
+        Ok(ColorPipeline {
+            exposure,
+            tone_mapping: self.tone_mapping,
+            lut,
+            dither: self.dither,
+        })
+    }
+}
+
+#[derive(Args)]
+pub struct DenoiseOptions {
+    /// Filter the noise out of the image, guided by the albedo and normal of the first hits
+    #[arg(long)]
+    pub denoise: bool,
+
+    /// More iterations smooth wider areas
+    #[arg(long, default_value_t = Denoiser::default().iterations)]
+    pub denoise_iterations: u32,
+
+    /// Higher blurs across larger color differences, has to be above zero
+    #[arg(long, value_parser = parse_strength, default_value_t = Denoiser::default().color_sigma)]
+    pub denoise_strength: f32,
+
+    /// Also write the image as rendered, before denoising
+    #[arg(long, requires = "denoise")]
+    pub raw_output: Option<PathBuf>,
+}
+
+impl DenoiseOptions {
+    pub fn denoiser(&self) -> Option<Denoiser> {
+        self.denoise.then(|| Denoiser {
+            iterations: self.denoise_iterations,
+            color_sigma: self.denoise_strength,
+            ..Default::default()
+        })
+    }
+}
+
+fn parse_resolution(value: &str) -> Result<Vec2<u32>, String> {
+    let (width, height) = value
+        .split_once('x')
+        .ok_or_else(|| format!("Expected WIDTHxHEIGHT, got {value}"))?;
+
+    let width = width.parse::<u32>().map_err(|error| error.to_string())?;
+    let height = height.parse::<u32>().map_err(|error| error.to_string())?;
+
+    if width == 0 || height == 0 {
+        return Err("Resolution can't be zero".into());
+    }
+
+    Ok(Vec2::new(width, height))
+}
+
+fn parse_strength(value: &str) -> Result<f32, String> {
+    let strength = value.parse::<f32>().map_err(|error| error.to_string())?;
+
+    if strength.is_nan() || strength <= 0. {
+        return Err("Strength has to be above zero".into());
+    }
+
+    Ok(strength)
+}
@@ -0,0 +1,203 @@
+use crate::lut::Lut;
+use clap::ValueEnum;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use shader::luminance;
+use vek::{Mat3, Vec3};
+
+/// Converts an sRGB encoded channel to linear
+pub fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Converts a linear channel to sRGB encoding
+pub fn linear_to_srgb(value: f32) -> f32 {
+    if value <= 0.0031308 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1. / 2.4) - 0.055
+    }
+}
+
+#[derive(Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum ToneMapping {
+    /// Cut off everything above one
+    #[default]
+    Clamp,
+
+    /// Reinhard on luminance, keeps the hue
+    Reinhard,
+
+    /// Fit of the ACES reference rendering and output transforms
+    Aces,
+
+    /// AgX, desaturates bright colors towards white
+    Agx,
+}
+
+/// Matrices are written row by row, vek stores them column by column
+fn rows(rows: [[f32; 3]; 3]) -> Mat3<f32> {
+    Mat3::from_row_arrays(rows)
+}
+
+/// Stephen Hill's fit, going from linear sRGB to the ACES rendering space and back
+fn aces(color: Vec3<f32>) -> Vec3<f32> {
+    let input = rows([
+        [0.59719, 0.35458, 0.04823],
+        [0.07600, 0.90834, 0.01566],
+        [0.02840, 0.13383, 0.83777],
+    ]);
+    let output = rows([
+        [1.60475, -0.53108, -0.07367],
+        [-0.10208, 1.10813, -0.00605],
+        [-0.00327, -0.07276, 1.07602],
+    ]);
+
+    let color = input * color;
+    let a = color * (color + 0.0245786) - 0.000090537;
+    let b = color * (0.983729 * color + 0.432951) + 0.238081;
+
+    output * (a / b)
+}
+
+/// Minimal AgX with the default look, from linear sRGB to linear display colors
+fn agx(color: Vec3<f32>) -> Vec3<f32> {
+    let inset = rows([
+        [0.842479, 0.0784336, 0.0792237],
+        [0.0423282, 0.878469, 0.0791661],
+        [0.0423757, 0.0784336, 0.879143],
+    ]);
+    let outset = rows([
+        [1.196879, -0.0980209, -0.0990297],
+        [-0.0528969, 1.151903, -0.0989612],
+        [-0.0529716, -0.0980435, 1.151074],
+    ]);
+
+    const MIN_EV: f32 = -12.47393;
+    const MAX_EV: f32 = 4.026069;
+
+    let color = inset * color;
+    let color = color.map(|c| ((c.max(1e-10).log2() - MIN_EV) / (MAX_EV - MIN_EV)).clamp(0., 1.));
+
+    // Polynomial approximation of the sigmoid contrast curve
+    let curve = color.map(|x| {
+        let x2 = x * x;
+        let x4 = x2 * x2;
+
+        15.5 * x4 * x2 - 40.14 * x4 * x + 31.96 * x4 - 6.868 * x2 * x + 0.4298 * x2 + 0.1191 * x
+            - 0.00232
+    });
+
+    // The curve produces display encoded colors, decode them so the sRGB transfer applies after
+    (outset * curve).map(|c| c.max(0.).powf(2.2))
+}
+
+impl ToneMapping {
+    /// Maps linear scene colors to linear display colors from zero to one
+    pub fn apply(self, color: Vec3<f32>) -> Vec3<f32> {
+        let color = match self {
+            ToneMapping::Clamp => color,
+            ToneMapping::Reinhard => color / (1. + luminance(color).max(0.)),
+            ToneMapping::Aces => aces(color),
+            ToneMapping::Agx => agx(color),
+        };
+
+        color.map(|c| c.clamp(0., 1.))
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum Exposure {
+    /// Scale by two to the power of the given stops
+    Manual(f32),
+
+    /// Scale the log-average luminance to middle gray, then adjust by the given stops
+    Auto(f32),
+}
+
+/// Scale that brings the log-average luminance of the image to middle gray
+fn auto_exposure(pixels: &[Vec3<f32>]) -> f32 {
+    const MIDDLE_GRAY: f32 = 0.18;
+
+    // The offset keeps black pixels from dragging the average to zero
+    let log_sum: f64 = pixels
+        .iter()
+        .map(|&pixel| (1e-4 + luminance(pixel).max(0.) as f64).ln())
+        .sum();
+    let log_average = (log_sum / pixels.len() as f64).exp() as f32;
+
+    MIDDLE_GRAY / log_average
+}
+
+/// Turns the linear colors from the renderer into display colors for low dynamic range formats:
+/// exposure, tone mapping, sRGB encoding, a lookup table and finally quantization
+pub struct ColorPipeline {
+    pub exposure: Exposure,
+    pub tone_mapping: ToneMapping,
+    pub lut: Option<Lut>,
+
+    /// Add noise of one quantization step before rounding, hiding banding
+    pub dither: bool,
+}
+
+impl Default for ColorPipeline {
+    fn default() -> Self {
+        Self {
+            exposure: Exposure::Manual(0.),
+            tone_mapping: ToneMapping::default(),
+            lut: None,
+            dither: false,
+        }
+    }
+}
+
+impl ColorPipeline {
+    fn exposure_scale(&self, pixels: &[Vec3<f32>]) -> f32 {
+        match self.exposure {
+            Exposure::Manual(stops) => stops.exp2(),
+            Exposure::Auto(stops) => auto_exposure(pixels) * stops.exp2(),
+        }
+    }
+
+    /// sRGB encoded display colors from zero to one
+    pub fn display(&self, pixels: &[Vec3<f32>]) -> Vec<Vec3<f32>> {
+        let scale = self.exposure_scale(pixels);
+
+        pixels
+            .iter()
+            .map(|&pixel| {
+                let color = self.tone_mapping.apply(pixel * scale).map(linear_to_srgb);
+
+                match &self.lut {
+                    Some(lut) => lut.apply(color).map(|c| c.clamp(0., 1.)),
+                    None => color,
+                }
+            })
+            .collect()
+    }
+
+    /// Display colors scaled to whole numbers from zero to `max_value`
+    pub fn quantize(&self, pixels: &[Vec3<f32>], max_value: f32) -> Vec<Vec3<f32>> {
+        // Fixed seed, so the same render always produces the same image
+        let mut rng = StdRng::seed_from_u64(0);
+
+        self.display(pixels)
+            .into_iter()
+            .map(|color| {
+                color.map(|c| {
+                    // Triangular noise, the difference of two uniform values
+                    let noise = if self.dither {
+                        rng.gen::<f32>() - rng.gen::<f32>()
+                    } else {
+                        0.
+                    };
+
+                    (c * max_value + noise).round().clamp(0., max_value)
+                })
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,96 @@
+use crate::render::{read_tile, tiles, write_tile, RenderData};
+use rayon::prelude::*;
+use shader::{Accumulation, AovAccumulation, DispatchSettings, UVec3};
+use std::sync::atomic::{AtomicBool, Ordering};
+use vek::Vec2;
+
+/// Small enough that the tiles even out across threads, large enough to keep the overhead low
+const TILE_SIZE: Vec2<u32> = Vec2::new(32, 32);
+
+/// Runs the shader natively for every pixel of a tile, adding to the tile's accumulation
+fn render_tile(
+    data: &RenderData,
+    seeds: &[u32],
+    dispatch: DispatchSettings,
+    accumulation: &mut [Accumulation],
+    aovs: &mut [AovAccumulation],
+) {
+    let tile_size = dispatch.tile_size();
+
+    for y in 0..tile_size.y {
+        for x in 0..tile_size.x {
+            shader::main(
+                UVec3 { x, y, z: 0 },
+                &dispatch,
+                &data.raytrace_settings,
+                &data.spheres,
+                &data.sphere_bvh_nodes,
+                &data.vertices,
+                &data.triangles,
+                &data.triangle_bvh_nodes,
+                &data.lights,
+                &data.textures,
+                &data.texels,
+                &data.environment_distribution,
+                &data.blue_noise,
+                seeds,
+                accumulation,
+                aovs,
+            );
+        }
+    }
+}
+
+/// Adds `amount_of_samples` samples to every pixel, starting at the seed at `sample_offset`.
+/// Tiles render in parallel, running the same shader code as the gpu. Once `interrupted` is set
+/// the tiles that haven't started are skipped, returns whether all of them were rendered
+pub fn cpu_pass(
+    data: &RenderData,
+    seeds: &[u32],
+    accumulation: &mut [Accumulation],
+    aovs: &mut [AovAccumulation],
+    sample_offset: u32,
+    amount_of_samples: u32,
+    interrupted: &AtomicBool,
+) -> bool {
+    let screen_size = data.raytrace_settings.screen_size;
+    let write_aovs = data.raytrace_settings.write_aovs != 0;
+    let tiles = tiles(screen_size, TILE_SIZE);
+
+    let rendered_tiles: Vec<Option<(Vec<Accumulation>, Vec<AovAccumulation>)>> = tiles
+        .par_iter()
+        .map(|&(tile_offset, tile_size)| {
+            if interrupted.load(Ordering::Relaxed) {
+                return None;
+            }
+
+            let mut tile = read_tile(accumulation, screen_size, tile_offset, tile_size);
+            let mut aov_tile = if write_aovs {
+                read_tile(aovs, screen_size, tile_offset, tile_size)
+            } else {
+                Vec::new()
+            };
+
+            let dispatch =
+                DispatchSettings::new(tile_offset, tile_size, sample_offset, amount_of_samples);
+            render_tile(data, seeds, dispatch, &mut tile, &mut aov_tile);
+
+            Some((tile, aov_tile))
+        })
+        .collect();
+
+    let mut finished = true;
+    for (&(tile_offset, tile_size), rendered_tile) in tiles.iter().zip(rendered_tiles) {
+        let Some((tile, aov_tile)) = rendered_tile else {
+            finished = false;
+            continue;
+        };
+
+        write_tile(accumulation, screen_size, tile_offset, tile_size, &tile);
+        if write_aovs {
+            write_tile(aovs, screen_size, tile_offset, tile_size, &aov_tile);
+        }
+    }
+
+    finished
+}
@@ -0,0 +1,129 @@
+use rayon::prelude::*;
+use shader::{luminance, Accumulation, AovAccumulation};
+use vek::{Vec2, Vec3};
+
+/// B3 spline, the weights of the 5x5 kernel along each axis
+const KERNEL: [f32; 5] = [1. / 16., 1. / 4., 3. / 8., 1. / 4., 1. / 16.];
+
+/// Albedos darker than this aren't divided out, the color would blow up
+const MIN_ALBEDO: f32 = 1e-3;
+
+/// Edge-avoiding À-trous wavelet filter, Dammertz et al. 2010. Every iteration blurs with the
+/// same kernel spread twice as far, weighing neighbours down that differ in color, albedo or normal
+#[derive(Clone, Copy)]
+pub struct Denoiser {
+    /// Each one doubles the radius, five reach 62 pixels out
+    pub iterations: u32,
+
+    /// How different colors may get before they stop blurring into each other, halved every
+    /// iteration so later wide steps only smooth what's left of the noise
+    pub color_sigma: f32,
+
+    /// The same for albedos and normals, kept over the iterations
+    pub albedo_sigma: f32,
+    pub normal_sigma: f32,
+}
+
+impl Default for Denoiser {
+    fn default() -> Self {
+        Self {
+            iterations: 5,
+            color_sigma: 2.,
+            albedo_sigma: 0.1,
+            normal_sigma: 0.3,
+        }
+    }
+}
+
+/// Per channel, where the albedo is dark enough to divide by
+fn demodulate(color: Vec3<f32>, albedo: Vec3<f32>) -> Vec3<f32> {
+    color
+        .zip(albedo)
+        .map(|(c, a)| if a > MIN_ALBEDO { c / a } else { c })
+}
+
+fn remodulate(color: Vec3<f32>, albedo: Vec3<f32>) -> Vec3<f32> {
+    color
+        .zip(albedo)
+        .map(|(c, a)| if a > MIN_ALBEDO { c * a } else { c })
+}
+
+/// Compresses bright colors, so fireflies don't stop the filter from averaging them away
+fn compress(color: Vec3<f32>) -> Vec3<f32> {
+    color / (1. + luminance(color).max(0.))
+}
+
+fn weight(a: Vec3<f32>, b: Vec3<f32>, sigma: f32) -> f32 {
+    (-(a - b).magnitude_squared() / (sigma * sigma)).exp()
+}
+
+impl Denoiser {
+    /// The linear colors of the accumulation with the noise filtered out, guided by the first
+    /// hits in `aovs`. The albedo is divided out while filtering, so textures stay sharp
+    pub fn denoise(
+        &self,
+        accumulation: &[Accumulation],
+        aovs: &[AovAccumulation],
+        screen_size: Vec2<u32>,
+    ) -> Vec<Vec3<f32>> {
+        let albedos: Vec<_> = aovs.iter().map(|p| p.average_albedo()).collect();
+        let normals: Vec<_> = aovs.iter().map(|p| p.average_normal()).collect();
+
+        let mut colors: Vec<_> = accumulation
+            .iter()
+            .zip(&albedos)
+            .map(|(pixel, &albedo)| demodulate(pixel.average(), albedo))
+            .collect();
+
+        let width = screen_size.x as usize;
+        let size = screen_size.as_::<i32>();
+
+        for iteration in 0..self.iterations {
+            let step = 1 << iteration;
+            let color_sigma = self.color_sigma / (1 << iteration) as f32;
+
+            colors = (0..colors.len())
+                .into_par_iter()
+                .map(|index| {
+                    let position = Vec2::new(index % width, index / width).as_::<i32>();
+                    let color = compress(colors[index]);
+
+                    let mut sum = Vec3::zero();
+                    let mut total_weight = 0.;
+
+                    for (y, kernel_y) in KERNEL.iter().enumerate() {
+                        for (x, kernel_x) in KERNEL.iter().enumerate() {
+                            let neighbour = position + (Vec2::new(x, y).as_::<i32>() - 2) * step;
+                            if neighbour.x < 0
+                                || neighbour.y < 0
+                                || neighbour.x >= size.x
+                                || neighbour.y >= size.y
+                            {
+                                continue;
+                            }
+                            let neighbour = (neighbour.y * size.x + neighbour.x) as usize;
+
+                            let weight = kernel_x
+                                * kernel_y
+                                * weight(color, compress(colors[neighbour]), color_sigma)
+                                * weight(albedos[index], albedos[neighbour], self.albedo_sigma)
+                                * weight(normals[index], normals[neighbour], self.normal_sigma);
+
+                            sum += colors[neighbour] * weight;
+                            total_weight += weight;
+                        }
+                    }
+
+                    // The pixel itself always counts fully, so the weight can't be zero
+                    sum / total_weight
+                })
+                .collect();
+        }
+
+        colors
+            .into_iter()
+            .zip(albedos)
+            .map(|(color, albedo)| remodulate(color, albedo))
+            .collect()
+    }
+}
@@ -0,0 +1,76 @@
+use crate::texture::TextureAtlas;
+use shader::EnvironmentSettings;
+
+/// An equirectangular environment map, loaded into the texture atlas
+#[derive(Clone, Copy)]
+pub struct EnvironmentMap {
+    pub texture: u32,
+
+    /// Rotation around the y axis, in radians
+    pub rotation: f32,
+    pub intensity: f32,
+}
+
+impl From<EnvironmentMap> for EnvironmentSettings {
+    fn from(environment: EnvironmentMap) -> Self {
+        Self {
+            texture: environment.texture,
+            rotation: environment.rotation,
+            intensity: environment.intensity,
+        }
+    }
+}
+
+/// Normalizes running sums of `weights` into a cumulative distribution starting at zero and
+/// ending at one, evenly spread when every weight is zero
+fn cumulative_distribution(weights: &[f32]) -> Vec<f32> {
+    let mut cdf = Vec::with_capacity(weights.len() + 1);
+    let mut sum = 0.;
+
+    cdf.push(0.);
+    for &weight in weights {
+        sum += weight;
+        cdf.push(sum);
+    }
+
+    if sum > 0. {
+        cdf.iter_mut().for_each(|value| *value /= sum);
+    } else {
+        let amount = weights.len() as f32;
+        cdf.iter_mut()
+            .enumerate()
+            .for_each(|(i, value)| *value = i as f32 / amount);
+    }
+
+    cdf
+}
+
+/// Builds the distribution the shader importance samples the map with: a cumulative distribution
+/// over the rows followed by one over the columns of every row. Texels are weighted by their
+/// luminance, and by the solid angle they cover which shrinks towards the poles
+pub fn build_distribution(textures: &TextureAtlas, environment: EnvironmentMap) -> Vec<f32> {
+    let texture = textures.textures[environment.texture as usize];
+    let (width, height) = (texture.size.x as usize, texture.size.y as usize);
+    let offset = texture.offset as usize;
+
+    let mut row_weights = Vec::with_capacity(height);
+    let mut row_distributions = Vec::with_capacity(height * (width + 1));
+
+    for y in 0..height {
+        let sin_theta = (std::f32::consts::PI * (y as f32 + 0.5) / height as f32).sin();
+
+        let row = &textures.texels[offset + y * width..offset + (y + 1) * width];
+        let weights: Vec<f32> = row
+            .iter()
+            .map(|texel| texel.dot([0.2126, 0.7152, 0.0722].into()) * sin_theta)
+            .collect();
+
+        row_weights.push(weights.iter().sum());
+        row_distributions.extend(cumulative_distribution(&weights));
+    }
+
+    let mut distribution = cumulative_distribution(&row_weights);
+    distribution.extend(row_distributions);
+
+    distribution
+}
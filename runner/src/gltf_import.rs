@@ -0,0 +1,255 @@
+use crate::{camera::Camera, mesh::MeshData, texture::TextureAtlas};
+use gltf::{camera::Projection, image::Format, mesh::Mode, Node};
+use image::{DynamicImage, ImageBuffer};
+use shader::{Material, Triangle, Vertex};
+use std::path::Path;
+use vek::{Mat4, Vec2, Vec3, Vec4};
+
+struct Importer<'a> {
+    buffers: Vec<gltf::buffer::Data>,
+    images: Vec<gltf::image::Data>,
+    camera: Option<Camera>,
+    mesh: MeshData,
+
+    /// Indices into `textures` of the images that were already added
+    texture_indices: Vec<Option<u32>>,
+    textures: &'a mut TextureAtlas,
+}
+
+/// Turns decoded image data back into an image, wider channels are stored in native byte order
+fn convert_image(data: &gltf::image::Data) -> Option<DynamicImage> {
+    let (width, height) = (data.width, data.height);
+
+    let bytes = || data.pixels.clone();
+    let words = || -> Vec<u16> {
+        data.pixels
+            .chunks_exact(2)
+            .map(|chunk| u16::from_ne_bytes([chunk[0], chunk[1]]))
+            .collect()
+    };
+    let floats = || -> Vec<f32> {
+        data.pixels
+            .chunks_exact(4)
+            .map(|chunk| f32::from_ne_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+            .collect()
+    };
+
+    let image = match data.format {
+        Format::R8 => DynamicImage::ImageLuma8(ImageBuffer::from_raw(width, height, bytes())?),
+        Format::R8G8 => DynamicImage::ImageLumaA8(ImageBuffer::from_raw(width, height, bytes())?),
+        Format::R8G8B8 => DynamicImage::ImageRgb8(ImageBuffer::from_raw(width, height, bytes())?),
+        Format::R8G8B8A8 => {
+            DynamicImage::ImageRgba8(ImageBuffer::from_raw(width, height, bytes())?)
+        }
+        Format::R16 => DynamicImage::ImageLuma16(ImageBuffer::from_raw(width, height, words())?),
+        Format::R16G16 => {
+            DynamicImage::ImageLumaA16(ImageBuffer::from_raw(width, height, words())?)
+        }
+        Format::R16G16B16 => {
+            DynamicImage::ImageRgb16(ImageBuffer::from_raw(width, height, words())?)
+        }
+        Format::R16G16B16A16 => {
+            DynamicImage::ImageRgba16(ImageBuffer::from_raw(width, height, words())?)
+        }
+        Format::R32G32B32FLOAT => {
+            DynamicImage::ImageRgb32F(ImageBuffer::from_raw(width, height, floats())?)
+        }
+        Format::R32G32B32A32FLOAT => {
+            DynamicImage::ImageRgba32F(ImageBuffer::from_raw(width, height, floats())?)
+        }
+    };
+
+    Some(image)
+}
+
+/// Maps a metallic-roughness material onto the closest [`Material`], with `texture` as the base
+/// color texture
+fn convert_material(material: gltf::Material, texture: u32) -> Material {
+    let pbr = material.pbr_metallic_roughness();
+    let base_color = Vec4::from(pbr.base_color_factor());
+    let albedo = base_color.xyz();
+
+    let emission = Vec3::from(material.emissive_factor());
+    if emission.reduce_partial_max() > 0. {
+        return Material::emissive(emission, material.emissive_strength().unwrap_or(1.));
+    }
+
+    let transmission = material
+        .transmission()
+        .map_or(0., |transmission| transmission.transmission_factor());
+
+    if transmission > 0.5 {
+        Material::glass(material.ior().unwrap_or(1.5))
+    } else if pbr.metallic_factor() > 0.5 {
+        Material::metal(albedo, pbr.roughness_factor()).with_texture(texture)
+    } else {
+        Material::diffuse(albedo).with_texture(texture)
+    }
+}
+
+fn convert_camera(camera: gltf::Camera, transform: Mat4<f32>) -> Option<Camera> {
+    let Projection::Perspective(perspective) = camera.projection() else {
+        eprintln!("Skipping orthographic camera");
+        return None;
+    };
+
+    // Cameras look down their local -z axis with y up
+    let position = transform.mul_point(Vec3::zero());
+    let forward = transform.mul_direction(-Vec3::unit_z()).normalized();
+    let up = transform.mul_direction(Vec3::unit_y()).normalized();
+
+    Some(Camera {
+        position,
+        target: position + forward,
+        up,
+
+        vertical_fov: perspective.yfov(),
+        defocus_angle: 0.,
+        focus_distance: 1.,
+    })
+}
+
+impl<'a> Importer<'a> {
+    /// Adds the base color texture of a material to the atlas the first time it's used
+    fn import_texture(&mut self, material: &gltf::Material) -> u32 {
+        let Some(info) = material.pbr_metallic_roughness().base_color_texture() else {
+            return 0;
+        };
+
+        if info.tex_coord() != 0 {
+            eprintln!("Only the first texture coordinates are supported");
+        }
+
+        let index = info.texture().source().index();
+        if let Some(texture) = self.texture_indices[index] {
+            return texture;
+        }
+
+        let texture = match convert_image(&self.images[index]) {
+            Some(image) => self.textures.add_image(image),
+            None => {
+                eprintln!("Skipping texture with invalid image data");
+                0
+            }
+        };
+
+        self.texture_indices[index] = Some(texture);
+        texture
+    }
+
+    fn import_mesh(&mut self, mesh: gltf::Mesh, transform: Mat4<f32>) {
+        let normal_transform = transform.inverted().transposed();
+
+        for primitive in mesh.primitives() {
+            if primitive.mode() != Mode::Triangles {
+                eprintln!("Skipping primitive with mode {:?}", primitive.mode());
+                continue;
+            }
+
+            let reader = primitive.reader(|buffer| Some(&self.buffers[buffer.index()]));
+
+            let Some(positions) = reader.read_positions() else {
+                continue;
+            };
+            let positions: Vec<Vec3<f32>> = positions
+                .map(|position| transform.mul_point(Vec3::from(position)))
+                .collect();
+
+            let normals: Vec<Vec3<f32>> = match reader.read_normals() {
+                Some(normals) => normals
+                    .map(|normal| {
+                        normal_transform
+                            .mul_direction(Vec3::from(normal))
+                            .normalized()
+                    })
+                    .collect(),
+                None => vec![Vec3::zero(); positions.len()],
+            };
+
+            let uvs: Vec<Vec2<f32>> = match reader.read_tex_coords(0) {
+                Some(uvs) => uvs.into_f32().map(Vec2::from).collect(),
+                None => vec![Vec2::zero(); positions.len()],
+            };
+
+            let indices: Vec<u32> = match reader.read_indices() {
+                Some(indices) => indices.into_u32().collect(),
+                None => (0..positions.len() as u32).collect(),
+            };
+
+            let vertices = positions
+                .into_iter()
+                .zip(normals)
+                .zip(uvs)
+                .map(|((position, normal), uv)| Vertex {
+                    position,
+                    normal,
+                    uv,
+                })
+                .collect();
+
+            let texture = self.import_texture(&primitive.material());
+            let material = convert_material(primitive.material(), texture);
+            let triangles = indices
+                .chunks_exact(3)
+                .map(|indices| Triangle {
+                    indices: Vec3::from_slice(indices),
+                    material,
+                    ..Default::default()
+                })
+                .collect();
+
+            self.mesh.extend(MeshData {
+                vertices,
+                triangles,
+            });
+        }
+    }
+
+    fn import_node(&mut self, node: Node, parent_transform: Mat4<f32>) {
+        let local_transform = Mat4::from_col_arrays(node.transform().matrix());
+        let transform = parent_transform * local_transform;
+
+        if let Some(mesh) = node.mesh() {
+            self.import_mesh(mesh, transform);
+        }
+
+        // The first camera in the hierarchy is used
+        if let (None, Some(camera)) = (self.camera, node.camera()) {
+            self.camera = convert_camera(camera, transform);
+        }
+
+        for child in node.children() {
+            self.import_node(child, transform);
+        }
+    }
+}
+
+/// Loads the default scene of a `.gltf` or `.glb` file, meshes become triangles with their base
+/// color textures added to `textures`, also returns the first perspective camera if there is one
+pub fn load_gltf(
+    path: impl AsRef<Path>,
+    textures: &mut TextureAtlas,
+) -> gltf::Result<(MeshData, Option<Camera>)> {
+    let (document, buffers, images) = gltf::import(path)?;
+
+    let mut importer = Importer {
+        buffers,
+        camera: None,
+        mesh: MeshData::default(),
+        texture_indices: vec![None; images.len()],
+        images,
+        textures,
+    };
+
+    let gltf_scene = document
+        .default_scene()
+        .or_else(|| document.scenes().next());
+
+    if let Some(gltf_scene) = gltf_scene {
+        for node in gltf_scene.nodes() {
+            importer.import_node(node, Mat4::identity());
+        }
+    }
+
+    Ok((importer.mesh, importer.camera))
+}
@@ -0,0 +1,479 @@
+use crate::render::{read_tile, tiles, write_tile, RenderData};
+use bevy_utils::default;
+use bytemuck::Pod;
+use shader::{Accumulation, AovAccumulation, DispatchSettings, WORKGROUP_SIZE};
+use std::{
+    mem::size_of,
+    sync::atomic::{AtomicBool, Ordering},
+};
+use vek::Vec2;
+use wgpu::{
+    include_spirv,
+    util::{BufferInitDescriptor, DeviceExt},
+    Backends, BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayoutDescriptor,
+    BindGroupLayoutEntry, BindingType, Buffer, BufferBindingType, BufferDescriptor, BufferUsages,
+    ComputePipeline, ComputePipelineDescriptor, Device, DeviceDescriptor, Features, Instance,
+    InstanceDescriptor, Limits, Maintain, PipelineLayoutDescriptor, Queue, ShaderStages,
+};
+
+/// Storage buffers can't be empty, empty slices are padded with one zeroed element that the bvh
+/// never references
+fn create_storage_buffer<T: Pod>(device: &Device, label: &str, contents: &[T]) -> Buffer {
+    let padding = [T::zeroed()];
+    let contents = if contents.is_empty() {
+        &padding
+    } else {
+        contents
+    };
+
+    device.create_buffer_init(&BufferInitDescriptor {
+        label: Some(label),
+        contents: bytemuck::cast_slice(contents),
+        usage: BufferUsages::STORAGE,
+    })
+}
+
+/// The scene uploaded to the gpu, ready to take passes over the image
+pub struct GpuRenderer {
+    device: Device,
+    queue: Queue,
+    compute_pipeline: ComputePipeline,
+    bind_group: BindGroup,
+    dispatch_buffer: Buffer,
+
+    /// Holds the accumulation of one tile
+    output_buffer: Buffer,
+
+    /// Holds the first hits of one tile, a placeholder when the passes are off
+    aov_output_buffer: Buffer,
+    write_aovs: bool,
+
+    screen_size: Vec2<u32>,
+    tile_size: Vec2<u32>,
+}
+
+impl GpuRenderer {
+    /// Uploads the scene and the seeds of the samples to take. Tiles of at most `tile_size` keep
+    /// the dispatches and the output buffer within the device limits
+    pub async fn new(data: &RenderData, seeds: &[u32], tile_size: Vec2<u32>) -> Self {
+        let shader = include_spirv!(env!("shader.spv"));
+        let screen_size = data.raytrace_settings.screen_size;
+
+        // Setup
+        let instance = Instance::new(InstanceDescriptor {
+            backends: Backends::PRIMARY,
+            ..default()
+        });
+
+        let adapter = instance
+            .request_adapter(&default())
+            .await
+            .expect("No adapter");
+
+        let (device, queue) = adapter
+            .request_device(
+                &DeviceDescriptor {
+                    label: Some("Device"),
+                    features: Features::MAPPABLE_PRIMARY_BUFFERS,
+                    // Every binding but the first is a storage buffer, more than the default limit
+                    // of eight
+                    limits: Limits {
+                        max_storage_buffers_per_shader_stage: adapter
+                            .limits()
+                            .max_storage_buffers_per_shader_stage,
+                        ..default()
+                    },
+                },
+                None,
+            )
+            .await
+            .unwrap();
+
+        let compute_shader_module = device.create_shader_module(shader);
+
+        // Data
+        let dispatch_buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("Dispatch buffer"),
+            size: size_of::<DispatchSettings>() as u64,
+            mapped_at_creation: false,
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        });
+
+        let raytrace_settings_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Raytrace settings buffer"),
+            contents: bytemuck::bytes_of(&data.raytrace_settings),
+            usage: BufferUsages::STORAGE,
+        });
+
+        let sphere_buffer = create_storage_buffer(&device, "Sphere buffer", &data.spheres);
+        let sphere_bvh_buffer =
+            create_storage_buffer(&device, "Sphere bvh buffer", &data.sphere_bvh_nodes);
+        let vertex_buffer = create_storage_buffer(&device, "Vertex buffer", &data.vertices);
+        let triangle_buffer = create_storage_buffer(&device, "Triangle buffer", &data.triangles);
+        let triangle_bvh_buffer =
+            create_storage_buffer(&device, "Triangle bvh buffer", &data.triangle_bvh_nodes);
+        let light_buffer = create_storage_buffer(&device, "Light buffer", &data.lights);
+        let texture_buffer = create_storage_buffer(&device, "Texture buffer", &data.textures);
+        let texel_buffer = create_storage_buffer(&device, "Texel buffer", &data.texels);
+        let environment_distribution_buffer = create_storage_buffer(
+            &device,
+            "Environment distribution buffer",
+            &data.environment_distribution,
+        );
+        let blue_noise_buffer =
+            create_storage_buffer(&device, "Blue noise buffer", &data.blue_noise);
+        let seed_buffer = create_storage_buffer(&device, "Seed buffer", seeds);
+
+        let tile_size = Vec2::partial_min(tile_size, screen_size);
+        let output_buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("Output buffer"),
+            size: tile_size.product() as u64 * (size_of::<Accumulation>() as u64),
+            mapped_at_creation: false,
+            usage: BufferUsages::STORAGE | BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+        });
+
+        let write_aovs = data.raytrace_settings.write_aovs != 0;
+        let aov_pixels = if write_aovs { tile_size.product() } else { 1 };
+        let aov_output_buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("Aov output buffer"),
+            size: aov_pixels as u64 * (size_of::<AovAccumulation>() as u64),
+            mapped_at_creation: false,
+            usage: BufferUsages::STORAGE | BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("Compute bind group layout"),
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 5,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 6,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 7,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 8,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 9,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 10,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 11,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 12,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 13,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 14,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let compute_pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("Compute pipeline layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let compute_pipeline = device.create_compute_pipeline(&ComputePipelineDescriptor {
+            label: Some("Compute pipeline"),
+            layout: Some(&compute_pipeline_layout),
+            module: &compute_shader_module,
+            entry_point: "main",
+        });
+
+        let bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: Some("Compute bind group"),
+            layout: &bind_group_layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: dispatch_buffer.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: raytrace_settings_buffer.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 2,
+                    resource: sphere_buffer.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 3,
+                    resource: sphere_bvh_buffer.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 4,
+                    resource: vertex_buffer.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 5,
+                    resource: triangle_buffer.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 6,
+                    resource: triangle_bvh_buffer.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 7,
+                    resource: light_buffer.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 8,
+                    resource: texture_buffer.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 9,
+                    resource: texel_buffer.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 10,
+                    resource: environment_distribution_buffer.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 11,
+                    resource: blue_noise_buffer.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 12,
+                    resource: seed_buffer.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 13,
+                    resource: output_buffer.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 14,
+                    resource: aov_output_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        Self {
+            device,
+            queue,
+            compute_pipeline,
+            bind_group,
+            dispatch_buffer,
+            output_buffer,
+            aov_output_buffer,
+            write_aovs,
+            screen_size,
+            tile_size,
+        }
+    }
+
+    /// Waits for the submitted dispatch and copies the first `length` values out of a buffer
+    async fn read_buffer<T: Pod>(&self, buffer: &Buffer, length: usize) -> Vec<T> {
+        let buffer_slice = buffer.slice(..);
+
+        let (sender, receiver) = flume::bounded(1);
+        buffer_slice.map_async(wgpu::MapMode::Read, move |v| sender.send(v).unwrap());
+
+        // Poll device to let it run the compute shader
+        self.device.poll(Maintain::Wait);
+
+        receiver
+            .recv_async()
+            .await
+            .expect("Flume")
+            .expect("Buffer map error");
+
+        let values = bytemuck::cast_slice(&buffer_slice.get_mapped_range())[..length].to_vec();
+        buffer.unmap();
+
+        values
+    }
+
+    /// Adds `amount_of_samples` samples to every pixel, tile by tile, starting at the seed at
+    /// `sample_offset`. The first hits are added to `aovs` when the passes are on. Stops before the
+    /// next tile once `interrupted` is set, returns whether all of them were rendered
+    pub async fn pass(
+        &self,
+        accumulation: &mut [Accumulation],
+        aovs: &mut [AovAccumulation],
+        sample_offset: u32,
+        amount_of_samples: u32,
+        interrupted: &AtomicBool,
+    ) -> bool {
+        for (tile_offset, tile_size) in tiles(self.screen_size, self.tile_size) {
+            if interrupted.load(Ordering::Relaxed) {
+                return false;
+            }
+
+            let workgroups = (tile_size + WORKGROUP_SIZE - 1) / WORKGROUP_SIZE;
+            let pixels = tile_size.product() as usize;
+
+            // The shader adds on top of what the tile has so far
+            let tile = read_tile(accumulation, self.screen_size, tile_offset, tile_size);
+            self.queue
+                .write_buffer(&self.output_buffer, 0, bytemuck::cast_slice(&tile));
+
+            if self.write_aovs {
+                let tile = read_tile(aovs, self.screen_size, tile_offset, tile_size);
+                self.queue
+                    .write_buffer(&self.aov_output_buffer, 0, bytemuck::cast_slice(&tile));
+            }
+
+            let dispatch =
+                DispatchSettings::new(tile_offset, tile_size, sample_offset, amount_of_samples);
+            self.queue
+                .write_buffer(&self.dispatch_buffer, 0, bytemuck::bytes_of(&dispatch));
+
+            let mut encoder = self.device.create_command_encoder(&default());
+            {
+                let mut compute_pass = encoder.begin_compute_pass(&default());
+                compute_pass.set_pipeline(&self.compute_pipeline);
+                compute_pass.set_bind_group(0, &self.bind_group, &[]);
+                compute_pass.dispatch_workgroups(workgroups.x, workgroups.y, 1);
+            }
+
+            self.queue.submit([encoder.finish()]);
+
+            let tile: Vec<Accumulation> = self.read_buffer(&self.output_buffer, pixels).await;
+            write_tile(
+                accumulation,
+                self.screen_size,
+                tile_offset,
+                tile_size,
+                &tile,
+            );
+
+            if self.write_aovs {
+                let tile: Vec<AovAccumulation> =
+                    self.read_buffer(&self.aov_output_buffer, pixels).await;
+                write_tile(aovs, self.screen_size, tile_offset, tile_size, &tile);
+            }
+        }
+
+        true
+    }
+}
@@ -0,0 +1,93 @@
+use std::{error::Error, fs, path::Path};
+use vek::Vec3;
+
+/// A 3D lookup table in the `.cube` format, applied to display encoded colors
+pub struct Lut {
+    size: usize,
+    domain_min: Vec3<f32>,
+    domain_max: Vec3<f32>,
+
+    /// Red changes fastest, then green, then blue
+    table: Vec<Vec3<f32>>,
+}
+
+fn parse_vec3(values: &[&str]) -> Result<Vec3<f32>, Box<dyn Error>> {
+    let [x, y, z] = values else {
+        return Err(format!("Expected three values, got {}", values.len()).into());
+    };
+
+    Ok(Vec3::new(x.parse()?, y.parse()?, z.parse()?))
+}
+
+impl Lut {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Box<dyn Error>> {
+        let contents = fs::read_to_string(path)?;
+
+        let mut size = None;
+        let mut domain_min = Vec3::zero();
+        let mut domain_max = Vec3::one();
+        let mut table = Vec::new();
+
+        for line in contents.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let words: Vec<&str> = line.split_whitespace().collect();
+
+            match words[0] {
+                "TITLE" => {}
+                "LUT_1D_SIZE" => return Err("1D lookup tables aren't supported".into()),
+                "LUT_3D_SIZE" => size = Some(words.get(1).ok_or("Missing size")?.parse()?),
+                "DOMAIN_MIN" => domain_min = parse_vec3(&words[1..])?,
+                "DOMAIN_MAX" => domain_max = parse_vec3(&words[1..])?,
+                _ => table.push(parse_vec3(&words)?),
+            }
+        }
+
+        let size: usize = size.ok_or("Missing LUT_3D_SIZE")?;
+        if size < 2 || table.len() != size * size * size {
+            return Err(format!(
+                "Expected {} entries for size {size}, got {}",
+                size * size * size,
+                table.len()
+            )
+            .into());
+        }
+
+        Ok(Self {
+            size,
+            domain_min,
+            domain_max,
+            table,
+        })
+    }
+
+    fn entry(&self, x: usize, y: usize, z: usize) -> Vec3<f32> {
+        self.table[x + self.size * (y + self.size * z)]
+    }
+
+    /// Looks up a color with trilinear interpolation, colors outside the domain are clamped
+    pub fn apply(&self, color: Vec3<f32>) -> Vec3<f32> {
+        let max_index = (self.size - 1) as f32;
+
+        let position = ((color - self.domain_min) / (self.domain_max - self.domain_min))
+            .map(|c| c.clamp(0., 1.) * max_index);
+
+        let low = position.map(|c| c.floor().min(max_index - 1.));
+        let fraction = position - low;
+        let low = low.map(|c| c as usize);
+
+        let lerp_x = |y, z| {
+            Vec3::lerp(
+                self.entry(low.x, y, z),
+                self.entry(low.x + 1, y, z),
+                fraction.x,
+            )
+        };
+        let lerp_y = |z| Vec3::lerp(lerp_x(low.y, z), lerp_x(low.y + 1, z), fraction.y);
+
+        Vec3::lerp(lerp_y(low.z), lerp_y(low.z + 1), fraction.z)
+    }
+}
@@ -1,308 +1,369 @@
+mod adaptive;
+mod aov;
+mod blue_noise;
+mod bvh;
+mod camera;
+mod checkpoint;
+mod cli;
+mod color;
+mod cpu;
+mod denoise;
+mod environment;
+mod gltf_import;
+mod gpu;
+mod lut;
+mod mesh;
+mod obj;
+mod output;
+mod render;
 mod scene;
-
-use bevy_utils::default;
-use rand::{thread_rng, Rng};
-use scene::scene;
-use shader::{RaytraceSettings, Sphere, UVec3, Viewport};
-use std::{fs, mem::size_of, time::Instant};
-use vek::{num_traits::Float, Vec2, Vec3};
-use wgpu::{
-    include_spirv,
-    util::{BufferInitDescriptor, DeviceExt},
-    Backends, BindGroupDescriptor, BindGroupEntry, BindGroupLayoutDescriptor, BindGroupLayoutEntry,
-    BindingType, BufferBindingType, BufferDescriptor, BufferUsages, ComputePipelineDescriptor,
-    DeviceDescriptor, Features, Instance, InstanceDescriptor, Maintain, PipelineLayoutDescriptor,
-    ShaderStages,
+mod scene_file;
+mod texture;
+
+#[cfg(test)]
+mod tests;
+
+use aov::write_image_with_aovs;
+use bvh::bvh_depth;
+use checkpoint::Checkpoint;
+use clap::{Parser, ValueEnum};
+use cli::{Cli, Command, RenderArgs};
+use output::{write_image, write_metadata_file, OutputFormat};
+use render::{average, average_path_length, master_seed, render, sample_seeds, RenderData};
+use scene::{load_scene, scene, Scene};
+use scene_file::SceneFile;
+use shader::{Accumulation, AovAccumulation};
+use std::{
+    ops::ControlFlow,
+    process,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Instant,
 };
 
-pub struct Camera {
-    pub position: Vec3<f32>,
-    pub target: Vec3<f32>,
-    pub up: Vec3<f32>,
-
-    pub vertical_fov: f32,
-    pub defocus_angle: f32,
-    pub focus_distance: f32,
-}
-
-fn calculate_viewport(camera: Camera, screen_size: Vec2<u32>) -> Viewport {
-    let aspect_ratio = (screen_size.x as f32) / (screen_size.y as f32);
-
-    let h = Float::tan(camera.vertical_fov / 2.);
-    let height = 2.0 * h * camera.focus_distance;
-    let width = height * aspect_ratio;
-
-    let w = (camera.position - camera.target).normalized();
-    let u = Vec3::cross(camera.up, w).normalized();
-    let v = Vec3::cross(w, u);
-
-    let horizontal = width * u;
-    let vertical = -height * v;
-
-    let horizontal_pixel_delta = horizontal / (screen_size.x as f32);
-    let vertical_pixel_delta = vertical / (screen_size.y as f32);
-
-    let upper_left_corner =
-        camera.position - (camera.focus_distance * w) - horizontal / 2. - vertical / 2.;
-
-    let upper_left_pixel_position =
-        upper_left_corner + horizontal_pixel_delta / 2. + vertical_pixel_delta / 2.;
-
-    let defocus_radius = camera.focus_distance * Float::tan(camera.defocus_angle / 2.);
-    let horizontal_defocus_disk = u * defocus_radius;
-    let vertical_defocus_disk = v * defocus_radius;
-
-    Viewport {
-        origin: camera.position,
-        upper_left_pixel_position,
-
-        horizontal_pixel_delta,
-        vertical_pixel_delta,
-
-        horizontal_defocus_disk,
-        vertical_defocus_disk,
+fn print_info(scene: Scene) {
+    let camera = scene.camera;
+    let settings = scene.settings;
+
+    println!(
+        "Camera: {} looking at {}, vertical fov {:.1}°",
+        camera.position,
+        camera.target,
+        camera.vertical_fov.to_degrees()
+    );
+    println!(
+        "Settings: {}x{}, {} samples, max depth {}",
+        settings.screen_size.x,
+        settings.screen_size.y,
+        settings.amount_of_samples,
+        settings.max_depth
+    );
+    if let Some(depth) = settings.russian_roulette_depth {
+        println!("Russian roulette after {depth} bounces");
     }
-}
-
-#[allow(dead_code)]
-fn render_cpu(raytrace_settings: &RaytraceSettings, spheres: &[Sphere]) -> Vec<Vec3<f32>> {
-    let screen_size = raytrace_settings.screen_size;
-
-    let mut output = vec![Vec3::<f32>::zero(); (screen_size.x * screen_size.y) as usize];
-
-    for _ in 0..raytrace_settings.amount_of_samples {
-        for y in 0..screen_size.y {
-            for x in 0..screen_size.x {
-                shader::main(
-                    UVec3 { x, y, z: 0 },
-                    &0,
-                    raytrace_settings,
-                    spheres,
-                    &mut output,
-                );
-            }
-        }
+    if let Some(seed) = settings.seed {
+        println!("Seed: {seed}");
     }
 
-    output
+    let data = RenderData::new(scene);
+
+    println!(
+        "Spheres: {}, bvh with {} nodes and depth {}",
+        data.spheres.len(),
+        data.sphere_bvh_nodes.len(),
+        bvh_depth(&data.sphere_bvh_nodes)
+    );
+    println!(
+        "Triangles: {} over {} vertices, bvh with {} nodes and depth {}",
+        data.triangles.len(),
+        data.vertices.len(),
+        data.triangle_bvh_nodes.len(),
+        bvh_depth(&data.triangle_bvh_nodes)
+    );
+    println!(
+        "Textures: {}, {} texels",
+        data.textures.len() - 1,
+        data.texels.len() - 1
+    );
 }
 
-#[pollster::main]
-async fn main() {
-    env_logger::init();
+async fn render_command(args: RenderArgs) {
+    let RenderArgs {
+        scene,
+        options,
+        color,
+        denoise,
+        output,
+        format,
+        metadata_file,
+        aov,
+        preview,
+        time_limit,
+        checkpoint: checkpoint_path,
+        checkpoint_interval,
+        resume,
+    } = args;
+
+    let format = format
+        .or_else(|| OutputFormat::from_path(&output))
+        .expect("Unknown output extension, pass a format");
+
+    let pipeline = color.pipeline().expect("Failed to load LUT");
+    let denoiser = denoise.denoiser();
+
+    let mut scene = load_scene(scene).expect("Failed to load scene");
+    options.apply(&mut scene.settings);
+
+    let scene_seed = scene.settings.seed;
+    let mut data = RenderData::new(scene);
+
+    // The denoiser is guided by the first hits as well
+    let write_aovs = !aov.is_empty() || denoiser.is_some();
+    data.raytrace_settings.write_aovs = write_aovs as u32;
+
+    let settings = data.raytrace_settings;
+    let scene_hash = checkpoint::scene_hash(&data);
+
+    let pixels = settings.screen_size.product() as usize;
+    let mut accumulation = vec![Accumulation::default(); pixels];
+    let mut aovs = if write_aovs {
+        vec![AovAccumulation::default(); pixels]
+    } else {
+        Vec::new()
+    };
+    let mut resumed_samples = 0;
 
-    let shader = include_spirv!(env!("shader.spv"));
+    let seed = match &checkpoint_path {
+        Some(path) if resume => {
+            let checkpoint = Checkpoint::load(path, scene_hash).expect("Failed to load checkpoint");
+            if scene_seed.is_some_and(|seed| seed != checkpoint.seed) {
+                eprintln!("Ignoring the seed, resuming with the checkpoint's");
+            }
 
-    let camera = Camera {
-        position: Vec3::new(13., 2., 3.),
-        target: Vec3::new(0., 0., 0.),
-        up: Vec3::new(0., 1., 0.),
+            accumulation = checkpoint.accumulation;
+            if write_aovs {
+                if checkpoint.aovs.is_empty() {
+                    eprintln!("Checkpoint has no first hit passes, they start from here");
+                } else {
+                    aovs = checkpoint.aovs;
+                }
+            }
+            resumed_samples = checkpoint.samples.min(settings.amount_of_samples);
+            eprintln!("Resuming at {resumed_samples} samples");
 
-        vertical_fov: (20.).to_radians(),
-        defocus_angle: (0.6).to_radians(),
-        focus_distance: 10.,
+            checkpoint.seed
+        }
+        _ => master_seed(scene_seed),
     };
+    eprintln!("Seed: {seed}");
+
+    let seeds = sample_seeds(seed, settings.amount_of_samples);
+
+    let backend = options.backend.to_possible_value().expect("Backend name");
+    let save = |accumulation: &[Accumulation], aovs: &[AovAccumulation]| {
+        let samples = accumulation.iter().map(|pixel| pixel.samples).min();
+        let metadata = [
+            ("Seed", seed.to_string()),
+            ("Samples", samples.unwrap_or_default().to_string()),
+            ("Max depth", settings.max_depth.to_string()),
+            (
+                "Russian roulette depth",
+                if settings.russian_roulette_depth < settings.max_depth {
+                    settings.russian_roulette_depth.to_string()
+                } else {
+                    "off".to_string()
+                },
+            ),
+            ("Backend", backend.get_name().to_string()),
+        ];
+
+        let raw = average(accumulation);
+        let image = match denoiser {
+            Some(denoiser) => {
+                if let Some(raw_output) = &denoise.raw_output {
+                    let format = OutputFormat::from_path(raw_output).unwrap_or(format);
+                    write_image(
+                        raw_output,
+                        format,
+                        settings.screen_size,
+                        &raw,
+                        &pipeline,
+                        &metadata,
+                    )
+                    .expect("Failed to write raw image");
+                }
+
+                denoiser.denoise(accumulation, aovs, settings.screen_size)
+            }
+            None => raw,
+        };
+
+        write_image_with_aovs(
+            &output,
+            format,
+            settings.screen_size,
+            &image,
+            accumulation,
+            aovs,
+            &aov,
+            &pipeline,
+            &metadata,
+        )
+        .expect("Failed to write image");
 
-    let screen_size = Vec2::new(800, 400);
-    let viewport = calculate_viewport(camera, screen_size);
-
-    let amount_of_samples = 10;
-    let max_depth = 50;
-
-    let raytrace_settings = RaytraceSettings {
-        viewport,
-        screen_size,
-        amount_of_samples,
-        max_depth,
+        if metadata_file {
+            write_metadata_file(&output, &metadata).expect("Failed to write metadata");
+        }
     };
 
-    let spheres = scene();
-
-    // Setup
-    let instance = Instance::new(InstanceDescriptor {
-        backends: Backends::PRIMARY,
-        ..default()
-    });
-
-    let adapter = instance
-        .request_adapter(&default())
-        .await
-        .expect("No adapter");
-
-    let (device, queue) = adapter
-        .request_device(
-            &DeviceDescriptor {
-                label: Some("Device"),
-                features: Features::MAPPABLE_PRIMARY_BUFFERS,
-                limits: default(),
-            },
-            None,
-        )
-        .await
-        .unwrap();
-
-    let compute_shader_module = device.create_shader_module(shader);
-
-    // Data
-    let seed_buffer = device.create_buffer(&BufferDescriptor {
-        label: Some("Seed buffer"),
-        size: size_of::<u32>() as u64,
-        mapped_at_creation: false,
-        usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
-    });
-
-    let raytrace_settings_buffer = device.create_buffer_init(&BufferInitDescriptor {
-        label: Some("Raytrace settings buffer"),
-        contents: bytemuck::bytes_of(&raytrace_settings),
-        usage: BufferUsages::STORAGE,
-    });
-
-    let sphere_buffer = device.create_buffer_init(&BufferInitDescriptor {
-        label: Some("Sphere buffer"),
-        contents: bytemuck::cast_slice(spheres.as_slice()),
-        usage: BufferUsages::STORAGE | BufferUsages::MAP_READ,
-    });
-
-    let output_buffer = device.create_buffer(&BufferDescriptor {
-        label: Some("Output buffer"),
-        size: (screen_size[0] * screen_size[1]) as u64 * (size_of::<Vec3<f32>>() as u64),
-        mapped_at_creation: false,
-        usage: BufferUsages::STORAGE | BufferUsages::MAP_READ,
-    });
-
-    let bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
-        label: Some("Compute bind group layout"),
-        entries: &[
-            BindGroupLayoutEntry {
-                binding: 0,
-                visibility: ShaderStages::COMPUTE,
-                ty: BindingType::Buffer {
-                    ty: BufferBindingType::Uniform,
-                    has_dynamic_offset: false,
-                    min_binding_size: None,
-                },
-                count: None,
-            },
-            BindGroupLayoutEntry {
-                binding: 1,
-                visibility: ShaderStages::COMPUTE,
-                ty: BindingType::Buffer {
-                    ty: BufferBindingType::Storage { read_only: true },
-                    has_dynamic_offset: false,
-                    min_binding_size: None,
-                },
-                count: None,
-            },
-            BindGroupLayoutEntry {
-                binding: 2,
-                visibility: ShaderStages::COMPUTE,
-                ty: BindingType::Buffer {
-                    ty: BufferBindingType::Storage { read_only: true },
-                    has_dynamic_offset: false,
-                    min_binding_size: None,
-                },
-                count: None,
-            },
-            BindGroupLayoutEntry {
-                binding: 3,
-                visibility: ShaderStages::COMPUTE,
-                ty: BindingType::Buffer {
-                    ty: BufferBindingType::Storage { read_only: false },
-                    has_dynamic_offset: false,
-                    min_binding_size: None,
-                },
-                count: None,
-            },
-        ],
-    });
-
-    let compute_pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
-        label: Some("Compute pipeline layout"),
-        bind_group_layouts: &[&bind_group_layout],
-        push_constant_ranges: &[],
-    });
-
-    let compute_pipeline = device.create_compute_pipeline(&ComputePipelineDescriptor {
-        label: Some("Compute pipeline"),
-        layout: Some(&compute_pipeline_layout),
-        module: &compute_shader_module,
-        entry_point: "main",
-    });
-
-    let bind_group = device.create_bind_group(&BindGroupDescriptor {
-        label: Some("Compute bind group"),
-        layout: &bind_group_layout,
-        entries: &[
-            BindGroupEntry {
-                binding: 0,
-                resource: seed_buffer.as_entire_binding(),
-            },
-            BindGroupEntry {
-                binding: 1,
-                resource: raytrace_settings_buffer.as_entire_binding(),
-            },
-            BindGroupEntry {
-                binding: 2,
-                resource: sphere_buffer.as_entire_binding(),
-            },
-            BindGroupEntry {
-                binding: 3,
-                resource: output_buffer.as_entire_binding(),
-            },
-        ],
-    });
-
-    let mut rng = thread_rng();
+    let save_checkpoint =
+        |accumulation: &[Accumulation], aovs: &[AovAccumulation], samples: u32| {
+            if let Some(path) = &checkpoint_path {
+                let checkpoint = Checkpoint {
+                    scene_hash,
+                    seed,
+                    samples,
+                    screen_size: settings.screen_size,
+                    accumulation: accumulation.to_vec(),
+                    aovs: aovs.to_vec(),
+                };
+                checkpoint.save(path).expect("Failed to write checkpoint");
+            }
+        };
+
+    // The first Ctrl-C finishes the tiles being rendered and writes what's there, a second one
+    // exits
+    let interrupted = Arc::new(AtomicBool::new(false));
+    let handler_interrupted = interrupted.clone();
+    ctrlc::set_handler(move || {
+        if handler_interrupted.swap(true, Ordering::Relaxed) {
+            process::exit(130);
+        }
+        eprintln!("Stopping after these tiles, press Ctrl-C again to exit right away");
+    })
+    .expect("Failed to set Ctrl-C handler");
 
     let time_started = Instant::now();
-    for i in 0..amount_of_samples {
-        eprintln!("Sample {i}");
-
-        let seed = rng.gen::<u32>();
-        queue.write_buffer(&seed_buffer, 0, bytemuck::bytes_of(&seed));
-
-        let mut encoder = device.create_command_encoder(&default());
-        {
-            let mut compute_pass = encoder.begin_compute_pass(&default());
-            compute_pass.set_pipeline(&compute_pipeline);
-            compute_pass.set_bind_group(0, &bind_group, &[]);
-            compute_pass.dispatch_workgroups(screen_size[0], screen_size[1], 1);
-        }
+    let mut last_checkpoint = time_started;
+    let mut samples_done = resumed_samples;
+    render(
+        options.backend,
+        &data,
+        &seeds,
+        resumed_samples,
+        options.pass_settings(),
+        &mut accumulation,
+        &mut aovs,
+        &interrupted,
+        |accumulation, aovs, samples| {
+            samples_done = samples;
+
+            if preview {
+                save(accumulation, aovs);
+            }
 
-        queue.submit([encoder.finish()]);
-        device.poll(wgpu::MaintainBase::Wait);
-    }
+            if last_checkpoint.elapsed().as_secs_f32() >= checkpoint_interval {
+                save_checkpoint(accumulation, aovs, samples_done);
+                last_checkpoint = Instant::now();
+            }
+
+            let timed_out =
+                time_limit.is_some_and(|limit| time_started.elapsed().as_secs_f32() >= limit);
+            if timed_out || interrupted.load(Ordering::Relaxed) {
+                ControlFlow::Break(())
+            } else {
+                ControlFlow::Continue(())
+            }
+        },
+    )
+    .await;
 
     let elapsed_time = time_started.elapsed().as_secs_f32();
     eprintln!("Elapsed time: {elapsed_time:.2}");
+    eprintln!(
+        "Average path length: {:.2} rays",
+        average_path_length(&accumulation)
+    );
 
-    let buffer_slice = output_buffer.slice(..);
-
-    let (sender, receiver) = flume::bounded(1);
-    buffer_slice.map_async(wgpu::MapMode::Read, move |v| sender.send(v).unwrap());
+    save_checkpoint(&accumulation, &aovs, samples_done);
+    save(&accumulation, &aovs);
+}
 
-    // Poll device to let it run the compute shader
-    device.poll(Maintain::Wait);
+#[pollster::main]
+async fn main() {
+    env_logger::init();
 
-    receiver
-        .recv_async()
-        .await
-        .expect("Flume")
-        .expect("Buffer map error");
+    let cli = Cli::parse();
 
-    let buffer_view = buffer_slice.get_mapped_range();
-    let output_data: &[Vec3<f32>] = bytemuck::cast_slice(&*buffer_view);
+    match cli.command {
+        Command::Render(args) => render_command(args).await,
 
-    // Export to ppm
-    let mut output_ppm = String::new();
-    output_ppm += &format!("P3\n{} {}\n255\n", screen_size[0], screen_size[1]);
+        Command::Info { scene } => {
+            let scene = load_scene(scene).expect("Failed to load scene");
+            print_info(scene);
+        }
 
-    for pixel in output_data {
-        let pixel = pixel.map(|c| c.sqrt()); // map from linear to gamma 2
-        let pixel = pixel.map(|c| f32::round(c * 255.) as u8);
+        Command::Bench { options } => {
+            let seed = master_seed(options.seed);
+
+            let mut scene = scene(seed);
+            options.apply(&mut scene.settings);
+
+            let time_started = Instant::now();
+            let data = RenderData::new(scene);
+            let build_time = time_started.elapsed().as_secs_f32();
+
+            let settings = data.raytrace_settings;
+            let seeds = sample_seeds(seed, settings.amount_of_samples);
+
+            let mut accumulation =
+                vec![Accumulation::default(); settings.screen_size.product() as usize];
+
+            let time_started = Instant::now();
+            render(
+                options.backend,
+                &data,
+                &seeds,
+                0,
+                options.pass_settings(),
+                &mut accumulation,
+                &mut [],
+                &AtomicBool::new(false),
+                |_, _, _| ControlFlow::Continue(()),
+            )
+            .await;
+            let render_time = time_started.elapsed().as_secs_f32();
+
+            let paths = settings.screen_size.product() as f32 * seeds.len() as f32;
+
+            println!("Bvh build: {build_time:.3}s");
+            println!("Render: {render_time:.3}s");
+            println!(
+                "Samples per second: {:.2}",
+                seeds.len() as f32 / render_time
+            );
+            println!("Paths per second: {:.2}M", paths / render_time / 1e6);
+            println!(
+                "Average path length: {:.2} rays",
+                average_path_length(&accumulation)
+            );
+        }
 
-        output_ppm += &format!("{} {} {}\n", pixel[0], pixel[1], pixel[2]);
+        Command::Export {
+            output,
+            scene: scene_path,
+            seed,
+        } => {
+            let scene = match scene_path {
+                Some(path) => load_scene(path).expect("Failed to load scene"),
+                None => scene(master_seed(seed)),
+            };
+
+            SceneFile::from_scene(&scene)
+                .save(output)
+                .expect("Failed to save scene");
+        }
     }
-
-    fs::write("image.ppm", output_ppm).unwrap();
 }
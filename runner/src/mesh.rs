@@ -0,0 +1,22 @@
+use shader::{Triangle, Vertex};
+
+/// Host side triangle geometry, uploaded to the vertex and triangle buffers
+#[derive(Clone, Default)]
+pub struct MeshData {
+    pub vertices: Vec<Vertex>,
+    pub triangles: Vec<Triangle>,
+}
+
+impl MeshData {
+    /// Appends another mesh, offsetting its indices past the current vertices
+    pub fn extend(&mut self, other: MeshData) {
+        let offset = self.vertices.len() as u32;
+
+        self.vertices.extend(other.vertices);
+        self.triangles
+            .extend(other.triangles.into_iter().map(|triangle| Triangle {
+                indices: triangle.indices + offset,
+                ..triangle
+            }));
+    }
+}
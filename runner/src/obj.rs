@@ -0,0 +1,136 @@
+use crate::{mesh::MeshData, texture::TextureAtlas};
+use shader::{Material, Triangle, Vertex};
+use std::path::Path;
+use vek::{Vec2, Vec3};
+
+/// Loads the diffuse texture of a `.mtl` material, relative to the `.obj` file
+fn load_texture(material: &tobj::Material, directory: &Path, textures: &mut TextureAtlas) -> u32 {
+    let Some(path) = &material.diffuse_texture else {
+        return 0;
+    };
+
+    match textures.load(directory.join(path)) {
+        Ok(texture) => texture,
+        Err(error) => {
+            eprintln!("Failed to load texture {path}, leaving it out: {error}");
+            0
+        }
+    }
+}
+
+/// Maps a `.mtl` material onto the closest [`Material`], based on the illumination model
+fn convert_material(material: &tobj::Material, texture: u32) -> Material {
+    let diffuse = Vec3::from(material.diffuse.unwrap_or([0.5; 3]));
+    let dissolve = material.dissolve.unwrap_or(1.);
+
+    // Emission isn't part of the standard fields, tobj leaves it as text
+    let emission = material.unknown_param.get("Ke").and_then(|emission| {
+        let values: Vec<f32> = emission
+            .split_whitespace()
+            .map(str::parse)
+            .collect::<Result<_, _>>()
+            .ok()?;
+
+        Some(Vec3::from_slice(values.get(0..3)?))
+    });
+
+    if let Some(emission) = emission.filter(|emission| emission.reduce_partial_max() > 0.) {
+        return Material::emissive(emission, 1.);
+    }
+
+    match material.illumination_model {
+        // Refraction, or any transparency
+        Some(4 | 6 | 7) => Material::glass(material.optical_density.unwrap_or(1.5)),
+        _ if dissolve < 1. => Material::glass(material.optical_density.unwrap_or(1.5)),
+
+        // Reflection
+        Some(3 | 5) => {
+            let albedo = material.specular.map_or(diffuse, Vec3::from);
+
+            // Shininess goes from 0 to 1000, the sharper the less fuzz
+            let shininess = material.shininess.unwrap_or(0.).clamp(0., 1000.);
+            let fuzz = 1. - shininess / 1000.;
+
+            Material::metal(albedo, fuzz).with_texture(texture)
+        }
+
+        _ => Material::diffuse(diffuse).with_texture(texture),
+    }
+}
+
+/// Loads every model in an `.obj` file into one mesh, materials are read from the referenced
+/// `.mtl` files when they exist and their diffuse textures are added to `textures`
+pub fn load_obj(
+    path: impl AsRef<Path>,
+    textures: &mut TextureAtlas,
+) -> Result<MeshData, tobj::LoadError> {
+    let path = path.as_ref();
+    let directory = path.parent().unwrap_or(Path::new(""));
+
+    let (models, materials) = tobj::load_obj(path, &tobj::GPU_LOAD_OPTIONS)?;
+
+    let materials: Vec<Material> = match materials {
+        Ok(materials) => materials
+            .iter()
+            .map(|material| {
+                let texture = load_texture(material, directory, textures);
+                convert_material(material, texture)
+            })
+            .collect(),
+        Err(error) => {
+            eprintln!("Failed to load materials, using default: {error}");
+            Vec::new()
+        }
+    };
+
+    let mut mesh_data = MeshData::default();
+
+    for model in models {
+        let mesh = model.mesh;
+
+        let material = mesh
+            .material_id
+            .and_then(|id| materials.get(id).copied())
+            .unwrap_or_else(|| Material::diffuse(Vec3::broadcast(0.5)));
+
+        let vertices = (0..mesh.positions.len() / 3)
+            .map(|i| {
+                let position = Vec3::from_slice(&mesh.positions[3 * i..3 * i + 3]);
+
+                let normal = mesh
+                    .normals
+                    .get(3 * i..3 * i + 3)
+                    .map_or(Vec3::zero(), Vec3::from_slice);
+
+                // Obj has the v axis pointing up, textures are stored top to bottom
+                let uv = mesh
+                    .texcoords
+                    .get(2 * i..2 * i + 2)
+                    .map_or(Vec2::zero(), |uv| Vec2::new(uv[0], 1. - uv[1]));
+
+                Vertex {
+                    position,
+                    normal,
+                    uv,
+                }
+            })
+            .collect();
+
+        let triangles = mesh
+            .indices
+            .chunks_exact(3)
+            .map(|indices| Triangle {
+                indices: Vec3::from_slice(indices),
+                material,
+                ..Default::default()
+            })
+            .collect();
+
+        mesh_data.extend(MeshData {
+            vertices,
+            triangles,
+        });
+    }
+
+    Ok(mesh_data)
+}
@@ -0,0 +1,265 @@
+use crate::color::ColorPipeline;
+use clap::ValueEnum;
+use exr::prelude::{
+    AnyChannel, AnyChannels, AttributeValue, Encoding, FlatSamples, Image, Layer, LayerAttributes,
+    Text, WritableImage,
+};
+use image::{codecs::hdr::HdrEncoder, Rgb};
+use png::{BitDepth, ColorType};
+use std::{
+    error::Error,
+    ffi::OsStr,
+    fs::{self, File},
+    io::BufWriter,
+    path::Path,
+};
+use vek::{Vec2, Vec3};
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// 8-bit PNG
+    Png,
+
+    /// 16-bit PNG
+    Png16,
+
+    /// Binary portable pixmap
+    Ppm,
+
+    /// Portable float map, linear
+    Pfm,
+
+    /// Radiance RGBE, linear
+    Hdr,
+
+    /// OpenEXR with 32-bit float channels, linear
+    Exr,
+}
+
+impl OutputFormat {
+    /// Picks the format from the file extension, 8-bit for `.png`
+    pub fn from_path(path: impl AsRef<Path>) -> Option<Self> {
+        let extension = path.as_ref().extension().and_then(OsStr::to_str)?;
+
+        match extension.to_lowercase().as_str() {
+            "png" => Some(OutputFormat::Png),
+            "ppm" => Some(OutputFormat::Ppm),
+            "pfm" => Some(OutputFormat::Pfm),
+            "hdr" => Some(OutputFormat::Hdr),
+            "exr" => Some(OutputFormat::Exr),
+            _ => None,
+        }
+    }
+
+    /// Formats that store display colors from zero to one
+    pub fn is_low_dynamic_range(self) -> bool {
+        matches!(
+            self,
+            OutputFormat::Png | OutputFormat::Png16 | OutputFormat::Ppm
+        )
+    }
+}
+
+/// Display colors quantized to 8 bits per channel
+fn to_bytes(pixels: &[Vec3<f32>], pipeline: &ColorPipeline) -> Vec<u8> {
+    pipeline
+        .quantize(pixels, u8::MAX as f32)
+        .into_iter()
+        .flat_map(|pixel| pixel.map(|c| c as u8).into_array())
+        .collect()
+}
+
+/// Display colors quantized to 16 bits per channel, big endian like PNG stores them
+fn to_words(pixels: &[Vec3<f32>], pipeline: &ColorPipeline) -> Vec<u8> {
+    pipeline
+        .quantize(pixels, u16::MAX as f32)
+        .into_iter()
+        .flat_map(|pixel| pixel.into_array())
+        .flat_map(|c| (c as u16).to_be_bytes())
+        .collect()
+}
+
+/// Writes RGB data with the metadata as text chunks
+fn write_png(
+    path: &Path,
+    screen_size: Vec2<u32>,
+    depth: BitDepth,
+    data: &[u8],
+    metadata: &[(&str, String)],
+) -> Result<(), Box<dyn Error>> {
+    let writer = BufWriter::new(File::create(path)?);
+
+    let mut encoder = png::Encoder::new(writer, screen_size.x, screen_size.y);
+    encoder.set_color(ColorType::Rgb);
+    encoder.set_depth(depth);
+
+    for (key, value) in metadata {
+        encoder.add_text_chunk(key.to_string(), value.clone())?;
+    }
+
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(data)?;
+
+    Ok(writer.finish()?)
+}
+
+/// Metadata as `# key: value` lines, the comment syntax of PPM and Radiance HDR headers
+fn comment_lines(metadata: &[(&str, String)]) -> String {
+    metadata
+        .iter()
+        .map(|(key, value)| format!("# {key}: {value}\n"))
+        .collect()
+}
+
+/// Binary portable pixmap, with the metadata as comments in the header
+fn write_ppm(
+    path: &Path,
+    screen_size: Vec2<u32>,
+    pixels: &[Vec3<f32>],
+    pipeline: &ColorPipeline,
+    metadata: &[(&str, String)],
+) -> Result<(), Box<dyn Error>> {
+    let mut header = String::from("P6\n");
+    header += &comment_lines(metadata);
+    header += &format!("{} {}\n255\n", screen_size.x, screen_size.y);
+
+    let mut output = header.into_bytes();
+    output.extend(to_bytes(pixels, pipeline));
+
+    Ok(fs::write(path, output)?)
+}
+
+/// Portable float map, little endian with rows stored from the bottom up. The header has no room
+/// for the metadata, see [`write_metadata_file`]
+fn write_pfm(
+    path: &Path,
+    screen_size: Vec2<u32>,
+    pixels: &[Vec3<f32>],
+) -> Result<(), Box<dyn Error>> {
+    let mut output = format!("PF\n{} {}\n-1.0\n", screen_size.x, screen_size.y).into_bytes();
+
+    for row in pixels.chunks_exact(screen_size.x as usize).rev() {
+        for pixel in row {
+            for c in pixel.into_array() {
+                output.extend(c.to_le_bytes());
+            }
+        }
+    }
+
+    Ok(fs::write(path, output)?)
+}
+
+/// Radiance RGBE, with the metadata as comments after the signature line
+fn write_hdr(
+    path: &Path,
+    screen_size: Vec2<u32>,
+    pixels: &[Vec3<f32>],
+    metadata: &[(&str, String)],
+) -> Result<(), Box<dyn Error>> {
+    let pixels: Vec<Rgb<f32>> = pixels
+        .iter()
+        .map(|&pixel| Rgb(pixel.into_array()))
+        .collect();
+
+    let mut output = Vec::new();
+    HdrEncoder::new(&mut output).encode(&pixels, screen_size.x as usize, screen_size.y as usize)?;
+
+    let signature_end = output
+        .iter()
+        .position(|&byte| byte == b'\n')
+        .map_or(0, |newline| newline + 1);
+    output.splice(
+        signature_end..signature_end,
+        comment_lines(metadata).into_bytes(),
+    );
+
+    Ok(fs::write(path, output)?)
+}
+
+/// Writes the linear colors as a ZIP compressed EXR, with extra channels like `depth.Z` stored
+/// next to them and the metadata as string attributes
+pub fn write_layered_exr(
+    path: impl AsRef<Path>,
+    screen_size: Vec2<u32>,
+    pixels: &[Vec3<f32>],
+    layers: Vec<(String, Vec<f32>)>,
+    metadata: &[(&str, String)],
+) -> Result<(), Box<dyn Error>> {
+    let rgb = ["R", "G", "B"].into_iter().enumerate().map(|(i, name)| {
+        let values = pixels.iter().map(|pixel| pixel[i]).collect();
+        (name.to_string(), values)
+    });
+
+    let channels = rgb
+        .chain(layers)
+        .map(|(name, values)| AnyChannel::new(name.as_str(), FlatSamples::F32(values)))
+        .collect();
+
+    let mut attributes = LayerAttributes::default();
+    for (key, value) in metadata {
+        attributes.other.insert(
+            Text::from(*key),
+            AttributeValue::Text(Text::from(value.as_str())),
+        );
+    }
+
+    let layer = Layer::new(
+        (screen_size.x as usize, screen_size.y as usize),
+        attributes,
+        Encoding::SMALL_LOSSLESS,
+        AnyChannels::sort(channels),
+    );
+
+    Ok(Image::from_layer(layer).write().to_file(path)?)
+}
+
+/// Writes the metadata as `key: value` lines to a text file next to the image, `image.pfm.txt`
+pub fn write_metadata_file(
+    path: impl AsRef<Path>,
+    metadata: &[(&str, String)],
+) -> Result<(), Box<dyn Error>> {
+    let mut sidecar = path.as_ref().as_os_str().to_os_string();
+    sidecar.push(".txt");
+
+    let lines: String = metadata
+        .iter()
+        .map(|(key, value)| format!("{key}: {value}\n"))
+        .collect();
+
+    Ok(fs::write(sidecar, lines)?)
+}
+
+/// Writes the linear colors read back from the renderer. Low dynamic range formats go through the
+/// color pipeline, the others keep the linear values as is. The metadata, like the seed, is stored
+/// as PNG text chunks, PPM and HDR comments or EXR attributes, PFM leaves it out
+pub fn write_image(
+    path: impl AsRef<Path>,
+    format: OutputFormat,
+    screen_size: Vec2<u32>,
+    pixels: &[Vec3<f32>],
+    pipeline: &ColorPipeline,
+    metadata: &[(&str, String)],
+) -> Result<(), Box<dyn Error>> {
+    let path = path.as_ref();
+
+    match format {
+        OutputFormat::Png => write_png(
+            path,
+            screen_size,
+            BitDepth::Eight,
+            &to_bytes(pixels, pipeline),
+            metadata,
+        ),
+        OutputFormat::Png16 => write_png(
+            path,
+            screen_size,
+            BitDepth::Sixteen,
+            &to_words(pixels, pipeline),
+            metadata,
+        ),
+        OutputFormat::Ppm => write_ppm(path, screen_size, pixels, pipeline, metadata),
+        OutputFormat::Pfm => write_pfm(path, screen_size, pixels),
+        OutputFormat::Hdr => write_hdr(path, screen_size, pixels, metadata),
+        OutputFormat::Exr => write_layered_exr(path, screen_size, pixels, Vec::new(), metadata),
+    }
+}
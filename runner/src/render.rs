@@ -0,0 +1,322 @@
+use crate::{
+    adaptive::AdaptiveSampling,
+    blue_noise::blue_noise_mask,
+    bvh::{build_bvh, build_bvh_by},
+    camera::calculate_viewport,
+    cpu::cpu_pass,
+    environment::build_distribution,
+    gpu::GpuRenderer,
+    mesh::MeshData,
+    scene::{RenderSettings, Scene},
+    scene_file::BackgroundDescription,
+    texture::TextureAtlas,
+};
+use clap::ValueEnum;
+use rand::{rngs::StdRng, thread_rng, Rng, SeedableRng};
+use shader::{
+    Accumulation, AovAccumulation, Background, BvhNode, Material, RaytraceSettings, Reflection,
+    Sphere, Texture, Triangle, Vertex,
+};
+use std::{ops::ControlFlow, sync::atomic::AtomicBool};
+use vek::{Vec2, Vec3};
+
+/// A scene laid out the way the shader reads it, with the bvhs built
+pub struct RenderData {
+    pub raytrace_settings: RaytraceSettings,
+    pub spheres: Vec<Sphere>,
+    pub sphere_bvh_nodes: Vec<BvhNode>,
+    pub vertices: Vec<Vertex>,
+    pub triangles: Vec<Triangle>,
+    pub triangle_bvh_nodes: Vec<BvhNode>,
+
+    /// Indices of the emissive spheres, sampled directly by the shader
+    pub lights: Vec<u32>,
+
+    pub textures: Vec<Texture>,
+    pub texels: Vec<Vec3<f32>>,
+
+    /// Distribution for importance sampling the environment map, empty without one
+    pub environment_distribution: Vec<f32>,
+
+    /// Mask the blue noise sampler shifts the samples of every pixel by
+    pub blue_noise: Vec<f32>,
+}
+
+/// Numbers the spheres and then the triangles in scene order, before the bvhs reorder them.
+/// Identical materials share an id, in the order they first show up
+fn assign_ids(spheres: &mut [Sphere], triangles: &mut [Triangle]) {
+    let mut materials: Vec<Material> = Vec::new();
+    let mut material_id = |material: Material| {
+        let bytes = bytemuck::bytes_of(&material);
+
+        match materials
+            .iter()
+            .position(|other| bytemuck::bytes_of(other) == bytes)
+        {
+            Some(id) => id as u32,
+            None => {
+                materials.push(material);
+                materials.len() as u32 - 1
+            }
+        }
+    };
+
+    for (id, sphere) in spheres.iter_mut().enumerate() {
+        sphere.id = id as u32;
+        sphere.material.id = material_id(sphere.material);
+    }
+
+    let amount_of_spheres = spheres.len() as u32;
+    for (id, triangle) in triangles.iter_mut().enumerate() {
+        triangle.id = amount_of_spheres + id as u32;
+        triangle.material.id = material_id(triangle.material);
+    }
+}
+
+impl RenderData {
+    pub fn new(scene: Scene) -> Self {
+        let Scene {
+            camera,
+            settings:
+                RenderSettings {
+                    screen_size,
+                    amount_of_samples,
+                    max_depth,
+                    russian_roulette_depth,
+                    background,
+                    sampler,
+                    seed: _,
+                },
+            mut spheres,
+            mesh: MeshData {
+                vertices,
+                mut triangles,
+            },
+            textures,
+            environment,
+        } = scene;
+
+        assign_ids(&mut spheres, &mut triangles);
+
+        let sphere_bvh_nodes = build_bvh(&mut spheres);
+        let triangle_bvh_nodes =
+            build_bvh_by(&mut triangles, |triangle| triangle.get_aabb(&vertices));
+
+        // Building the bvh reorders the spheres, so the lights are found afterwards
+        let lights: Vec<u32> = spheres
+            .iter()
+            .enumerate()
+            .filter(|(_, sphere)| matches!(sphere.material.reflection, Reflection::Emissive))
+            .map(|(i, _)| i as u32)
+            .collect();
+
+        // The environment map replaces the sky, turning off the background turns off both
+        let (background, environment) = match (background, environment) {
+            (BackgroundDescription::Black, _) => (Background::Black, None),
+            (_, Some(environment)) => (Background::Environment, Some(environment)),
+            (background, None) => (background.into(), None),
+        };
+
+        let environment_distribution = environment
+            .map(|environment| build_distribution(&textures, environment))
+            .unwrap_or_default();
+
+        let raytrace_settings = RaytraceSettings {
+            viewport: calculate_viewport(camera, screen_size),
+            screen_size,
+            amount_of_samples,
+            max_depth,
+            russian_roulette_depth: russian_roulette_depth.unwrap_or(max_depth),
+            background,
+            environment: environment.map(Into::into).unwrap_or_default(),
+            amount_of_spheres: spheres.len() as u32,
+            amount_of_triangles: triangles.len() as u32,
+            amount_of_lights: lights.len() as u32,
+            sampler: sampler.into(),
+            write_aovs: 0,
+        };
+
+        let TextureAtlas { textures, texels } = textures;
+
+        Self {
+            raytrace_settings,
+            spheres,
+            sphere_bvh_nodes,
+            vertices,
+            triangles,
+            triangle_bvh_nodes,
+            lights,
+            textures,
+            texels,
+            environment_distribution,
+            blue_noise: blue_noise_mask().to_vec(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum Backend {
+    /// Compute shader through wgpu
+    #[default]
+    Gpu,
+
+    /// The same shader compiled natively, spread over all cores, for machines without a gpu
+    Cpu,
+}
+
+/// Splits the screen into tiles of at most `tile_size`, row by row, as offset and clipped size
+pub fn tiles(screen_size: Vec2<u32>, tile_size: Vec2<u32>) -> Vec<(Vec2<u32>, Vec2<u32>)> {
+    let mut tiles = Vec::new();
+
+    for y in (0..screen_size.y).step_by(tile_size.y as usize) {
+        for x in (0..screen_size.x).step_by(tile_size.x as usize) {
+            let offset = Vec2::new(x, y);
+            tiles.push((offset, Vec2::partial_min(tile_size, screen_size - offset)));
+        }
+    }
+
+    tiles
+}
+
+/// The given seed, or a random one to record for reproducing the render later
+pub fn master_seed(seed: Option<u64>) -> u64 {
+    seed.unwrap_or_else(|| thread_rng().gen())
+}
+
+/// One seed per sample, all derived from the master seed
+pub fn sample_seeds(seed: u64, amount_of_samples: u32) -> Vec<u32> {
+    let mut rng = StdRng::seed_from_u64(seed);
+
+    (0..amount_of_samples).map(|_| rng.gen()).collect()
+}
+
+/// Copies a tile out of the image, row by row at the tile's own width
+pub fn read_tile<T: Copy>(
+    image: &[T],
+    screen_size: Vec2<u32>,
+    tile_offset: Vec2<u32>,
+    tile_size: Vec2<u32>,
+) -> Vec<T> {
+    (0..tile_size.y)
+        .flat_map(|y| {
+            let start = ((tile_offset.y + y) * screen_size.x + tile_offset.x) as usize;
+            image[start..start + tile_size.x as usize].iter().copied()
+        })
+        .collect()
+}
+
+/// Copies a tile from [`read_tile`] back into the image
+pub fn write_tile<T: Copy>(
+    image: &mut [T],
+    screen_size: Vec2<u32>,
+    tile_offset: Vec2<u32>,
+    tile_size: Vec2<u32>,
+    tile: &[T],
+) {
+    for (y, row) in tile.chunks_exact(tile_size.x as usize).enumerate() {
+        let start = ((tile_offset.y + y as u32) * screen_size.x + tile_offset.x) as usize;
+        image[start..start + row.len()].copy_from_slice(row);
+    }
+}
+
+/// How a render is split up
+#[derive(Clone, Copy)]
+pub struct PassSettings {
+    /// Largest part of the image a gpu dispatch renders, the cpu picks its own tiles
+    pub tile_size: Vec2<u32>,
+
+    /// Samples added to every pixel in one pass over the image. More means fewer submits, fewer
+    /// means more frequent previews
+    pub samples_per_pass: u32,
+
+    /// Checked after every pass, pixels that are done get skipped by the following ones
+    pub adaptive: Option<AdaptiveSampling>,
+}
+
+/// The linear colors of the image so far
+pub fn average(accumulation: &[Accumulation]) -> Vec<Vec3<f32>> {
+    accumulation.iter().map(|pixel| pixel.average()).collect()
+}
+
+/// Rays traced per path over the whole image, shadow rays not counted
+pub fn average_path_length(accumulation: &[Accumulation]) -> f32 {
+    let rays: u64 = accumulation
+        .iter()
+        .map(|pixel| pixel.path_length as u64)
+        .sum();
+    let paths: u64 = accumulation.iter().map(|pixel| pixel.samples as u64).sum();
+
+    rays as f32 / paths.max(1) as f32
+}
+
+/// Adds one sample per seed from `first_sample` on to every pixel of the accumulation, in passes
+/// over the whole image. The first hits go to `aovs` when the settings ask for them, it's left
+/// alone and may be empty otherwise. After every pass, `on_pass` gets the image so far and the
+/// seeds used up, and may stop the render early, which still leaves a valid image behind. Setting
+/// `interrupted` stops it between tiles, the pass left unfinished isn't handed to `on_pass`
+#[allow(clippy::too_many_arguments)]
+pub async fn render(
+    backend: Backend,
+    data: &RenderData,
+    seeds: &[u32],
+    first_sample: u32,
+    settings: PassSettings,
+    accumulation: &mut [Accumulation],
+    aovs: &mut [AovAccumulation],
+    interrupted: &AtomicBool,
+    mut on_pass: impl FnMut(&[Accumulation], &[AovAccumulation], u32) -> ControlFlow<()>,
+) {
+    let gpu = match backend {
+        Backend::Gpu => Some(GpuRenderer::new(data, seeds, settings.tile_size).await),
+        Backend::Cpu => None,
+    };
+
+    let amount_of_samples = seeds.len() as u32;
+    let samples_per_pass = settings.samples_per_pass.max(1);
+
+    for sample_offset in (first_sample..amount_of_samples).step_by(samples_per_pass as usize) {
+        let samples = samples_per_pass.min(amount_of_samples - sample_offset);
+
+        let finished = match &gpu {
+            Some(gpu) => {
+                gpu.pass(accumulation, aovs, sample_offset, samples, interrupted)
+                    .await
+            }
+            None => cpu_pass(
+                data,
+                seeds,
+                accumulation,
+                aovs,
+                sample_offset,
+                samples,
+                interrupted,
+            ),
+        };
+
+        if !finished {
+            eprintln!(
+                "Stopped between tiles, short of {} samples",
+                sample_offset + samples
+            );
+            break;
+        }
+
+        eprintln!("Samples {} of {amount_of_samples}", sample_offset + samples);
+
+        let active_pixels = settings
+            .adaptive
+            .map(|adaptive| adaptive.update(accumulation));
+        if let Some(active_pixels) = active_pixels {
+            eprintln!(
+                "{active_pixels} of {} pixels still sampling",
+                accumulation.len()
+            );
+        }
+
+        if on_pass(accumulation, aovs, sample_offset + samples).is_break()
+            || active_pixels == Some(0)
+        {
+            break;
+        }
+    }
+}
@@ -1,39 +1,134 @@
-use rand::{thread_rng, Rng};
+use crate::{
+    camera::Camera,
+    environment::EnvironmentMap,
+    gltf_import::load_gltf,
+    mesh::MeshData,
+    obj::load_obj,
+    scene_file::{BackgroundDescription, SamplerDescription, SceneFile},
+    texture::TextureAtlas,
+};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
 use shader::{Material, Sphere};
-use vek::Vec3;
-
-pub fn scene() -> Vec<Sphere> {
-    let mut spheres = Vec::new();
-
-    // Ground
-    spheres.push(Sphere {
-        center: Vec3::new(0., -1000., 0.),
-        radius: 1000.,
-        material: Material::diffuse(Vec3::new(0.5, 0.5, 0.5)),
-    });
-
-    // Center sphere
-    spheres.push(Sphere {
-        center: Vec3::new(0., 1., 0.),
-        radius: 1.,
-        material: Material::glass(1.5),
-    });
-
-    // Left sphere
-    spheres.push(Sphere {
-        center: Vec3::new(-4., 1., 0.),
-        radius: 1.,
-        material: Material::diffuse(Vec3::new(0.4, 0.2, 0.1)),
-    });
-
-    // Right sphere
-    spheres.push(Sphere {
-        center: Vec3::new(4., 1., 0.),
-        radius: 1.,
-        material: Material::metal(Vec3::new(0.7, 0.6, 0.5), 0.),
-    });
-
-    let rng = &mut thread_rng();
+use std::{error::Error, ffi::OsStr, path::Path};
+use vek::{Vec2, Vec3};
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct RenderSettings {
+    pub screen_size: Vec2<u32>,
+    pub amount_of_samples: u32,
+    pub max_depth: u32,
+
+    /// Bounces every path takes before Russian roulette may end it, paths carrying little light
+    /// are ended more often and the ones that survive count for more. Off when left out, or at
+    /// `max_depth` or above
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub russian_roulette_depth: Option<u32>,
+    #[serde(default)]
+    pub background: BackgroundDescription,
+    #[serde(default)]
+    pub sampler: SamplerDescription,
+
+    /// Master seed for the sample seeds, random when left out
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        Self {
+            screen_size: Vec2::new(800, 400),
+            amount_of_samples: 10,
+            max_depth: 50,
+            russian_roulette_depth: None,
+            background: BackgroundDescription::Sky,
+            sampler: SamplerDescription::Independent,
+            seed: None,
+        }
+    }
+}
+
+/// Everything that gets rendered, before it's uploaded to the gpu
+pub struct Scene {
+    pub camera: Camera,
+    pub settings: RenderSettings,
+    pub spheres: Vec<Sphere>,
+    pub mesh: MeshData,
+    pub textures: TextureAtlas,
+    pub environment: Option<EnvironmentMap>,
+}
+
+impl Scene {
+    /// A scene with only a mesh, using the default camera and settings
+    pub fn from_mesh(mesh: MeshData, textures: TextureAtlas) -> Self {
+        Self {
+            camera: Camera::default(),
+            settings: RenderSettings::default(),
+            spheres: Vec::new(),
+            mesh,
+            textures,
+            environment: None,
+        }
+    }
+}
+
+/// Loads a scene file, or a single `.obj`, `.gltf` or `.glb` file, based on the extension
+pub fn load_scene(path: impl AsRef<Path>) -> Result<Scene, Box<dyn Error>> {
+    let path = path.as_ref();
+
+    let mut textures = TextureAtlas::default();
+
+    match path.extension().and_then(OsStr::to_str) {
+        Some("obj") => Ok(Scene::from_mesh(load_obj(path, &mut textures)?, textures)),
+        Some("gltf" | "glb") => {
+            let (mesh, camera) = load_gltf(path, &mut textures)?;
+
+            Ok(Scene {
+                camera: camera.unwrap_or_default(),
+                ..Scene::from_mesh(mesh, textures)
+            })
+        }
+        _ => SceneFile::load(path)?.into_scene(path.parent().unwrap_or(Path::new(""))),
+    }
+}
+
+/// The "random spheres" scene, the same seed generates the same scene. The seed is kept in the
+/// settings, so rendering the scene with them is reproducible too
+pub fn scene(seed: u64) -> Scene {
+    let camera = Camera::default();
+
+    let mut spheres = vec![
+        // Ground
+        Sphere {
+            center: Vec3::new(0., -1000., 0.),
+            radius: 1000.,
+            material: Material::diffuse(Vec3::new(0.5, 0.5, 0.5)),
+            ..Default::default()
+        },
+        // Center sphere
+        Sphere {
+            center: Vec3::new(0., 1., 0.),
+            radius: 1.,
+            material: Material::glass(1.5),
+            ..Default::default()
+        },
+        // Left sphere
+        Sphere {
+            center: Vec3::new(-4., 1., 0.),
+            radius: 1.,
+            material: Material::diffuse(Vec3::new(0.4, 0.2, 0.1)),
+            ..Default::default()
+        },
+        // Right sphere
+        Sphere {
+            center: Vec3::new(4., 1., 0.),
+            radius: 1.,
+            material: Material::metal(Vec3::new(0.7, 0.6, 0.5), 0.),
+            ..Default::default()
+        },
+    ];
+
+    let rng = &mut StdRng::seed_from_u64(seed);
 
     for a in -11..11 {
         for b in -11..11 {
@@ -60,6 +155,7 @@ pub fn scene() -> Vec<Sphere> {
                         center,
                         radius: 0.2,
                         material: Material::diffuse(albedo),
+                        ..Default::default()
                     });
                 } else if choose_material < 0.95 {
                     // Metal
@@ -71,17 +167,29 @@ pub fn scene() -> Vec<Sphere> {
                         center,
                         radius: 0.2,
                         material: Material::metal(albedo, fuzz),
+                        ..Default::default()
                     });
                 } else {
                     spheres.push(Sphere {
                         center,
                         radius: 0.2,
                         material: Material::glass(1.5),
+                        ..Default::default()
                     });
                 }
             }
         }
     }
 
-    spheres
+    Scene {
+        camera,
+        settings: RenderSettings {
+            seed: Some(seed),
+            ..RenderSettings::default()
+        },
+        spheres,
+        mesh: MeshData::default(),
+        textures: TextureAtlas::default(),
+        environment: None,
+    }
 }
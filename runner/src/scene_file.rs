@@ -0,0 +1,407 @@
+use crate::{
+    camera::{degrees, Camera},
+    environment::EnvironmentMap,
+    gltf_import::load_gltf,
+    mesh::MeshData,
+    obj::load_obj,
+    scene::{RenderSettings, Scene},
+    texture::TextureAtlas,
+};
+use clap::ValueEnum;
+use image::ImageResult;
+use ron::{extensions::Extensions, ser::PrettyConfig, Options};
+use serde::{Deserialize, Serialize};
+use shader::{Background, Material, Reflection, SamplerKind, Sphere, Texture};
+use std::{
+    error::Error,
+    ffi::OsStr,
+    fs,
+    path::{Path, PathBuf},
+};
+use vek::Vec3;
+
+#[derive(Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BackgroundDescription {
+    /// The gradient, or the environment map when the scene has one
+    #[default]
+    Sky,
+    Black,
+}
+
+impl From<BackgroundDescription> for Background {
+    fn from(description: BackgroundDescription) -> Self {
+        match description {
+            BackgroundDescription::Sky => Background::Sky,
+            BackgroundDescription::Black => Background::Black,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+pub enum SamplerDescription {
+    /// Independent random numbers
+    #[default]
+    Independent,
+
+    /// Owen-scrambled Sobol, converges faster
+    Sobol,
+
+    /// Sobol dithered across pixels with a blue noise mask, the noise left looks like fine grain
+    BlueNoise,
+}
+
+impl From<SamplerDescription> for SamplerKind {
+    fn from(description: SamplerDescription) -> Self {
+        match description {
+            SamplerDescription::Independent => SamplerKind::Independent,
+            SamplerDescription::Sobol => SamplerKind::Sobol,
+            SamplerDescription::BlueNoise => SamplerKind::BlueNoise,
+        }
+    }
+}
+
+/// Diffuse and metal materials can multiply their albedo with a texture, indexing into the scene's
+/// textures
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum MaterialDescription {
+    Diffuse {
+        albedo: Vec3<f32>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        texture: Option<usize>,
+    },
+    Metal {
+        albedo: Vec3<f32>,
+        fuzz: f32,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        texture: Option<usize>,
+    },
+    Glass {
+        refraction_index: f32,
+    },
+    Emissive {
+        color: Vec3<f32>,
+        intensity: f32,
+    },
+}
+
+impl From<MaterialDescription> for Material {
+    /// Textures are left out, they're resolved by the scene file
+    fn from(description: MaterialDescription) -> Self {
+        match description {
+            MaterialDescription::Diffuse { albedo, .. } => Material::diffuse(albedo),
+            MaterialDescription::Metal { albedo, fuzz, .. } => Material::metal(albedo, fuzz),
+            MaterialDescription::Glass { refraction_index } => Material::glass(refraction_index),
+            MaterialDescription::Emissive { color, intensity } => {
+                Material::emissive(color, intensity)
+            }
+        }
+    }
+}
+
+impl MaterialDescription {
+    fn texture(self) -> Option<usize> {
+        match self {
+            MaterialDescription::Diffuse { texture, .. }
+            | MaterialDescription::Metal { texture, .. } => texture,
+            _ => None,
+        }
+    }
+}
+
+impl From<Material> for MaterialDescription {
+    /// Textures are left out, the images they came from aren't known anymore
+    fn from(material: Material) -> Self {
+        match material.reflection {
+            Reflection::Diffuse => MaterialDescription::Diffuse {
+                albedo: material.albedo,
+                texture: None,
+            },
+            Reflection::Metal => MaterialDescription::Metal {
+                albedo: material.albedo,
+                fuzz: material.fuzz,
+                texture: None,
+            },
+            Reflection::Glass => MaterialDescription::Glass {
+                refraction_index: material.refraction_index,
+            },
+            Reflection::Emissive => MaterialDescription::Emissive {
+                color: material.albedo,
+                intensity: material.intensity,
+            },
+        }
+    }
+}
+
+fn default_octaves() -> u32 {
+    7
+}
+
+fn default_intensity() -> f32 {
+    1.
+}
+
+/// Equirectangular `.hdr` or `.exr` image lighting the scene
+#[derive(Clone, Serialize, Deserialize)]
+pub struct EnvironmentDescription {
+    /// Relative to the scene file
+    pub path: PathBuf,
+
+    /// Rotation around the y axis
+    #[serde(default, with = "degrees")]
+    pub rotation: f32,
+    #[serde(default = "default_intensity")]
+    pub intensity: f32,
+}
+
+/// Albedo textures, colors are blended between for procedural patterns
+#[derive(Clone, Serialize, Deserialize)]
+pub enum TextureDescription {
+    /// PNG, JPEG, Radiance HDR or OpenEXR image, relative to the scene file
+    Image(PathBuf),
+    Checker {
+        scale: f32,
+        even: Vec3<f32>,
+        odd: Vec3<f32>,
+    },
+    /// Perlin noise, turbulent when `octaves` is above zero
+    Noise {
+        scale: f32,
+        #[serde(default)]
+        octaves: u32,
+        low: Vec3<f32>,
+        high: Vec3<f32>,
+    },
+    Marble {
+        scale: f32,
+        #[serde(default = "default_octaves")]
+        octaves: u32,
+        low: Vec3<f32>,
+        high: Vec3<f32>,
+    },
+    Worley {
+        scale: f32,
+        near: Vec3<f32>,
+        far: Vec3<f32>,
+    },
+}
+
+impl TextureDescription {
+    /// Adds the texture to the atlas, loading images relative to `directory`
+    fn load(&self, directory: &Path, textures: &mut TextureAtlas) -> ImageResult<u32> {
+        let texture = match *self {
+            TextureDescription::Image(ref path) => return textures.load(directory.join(path)),
+            TextureDescription::Checker { scale, even, odd } => Texture::checker(scale, even, odd),
+            TextureDescription::Noise {
+                scale,
+                octaves,
+                low,
+                high,
+            } => Texture::noise(scale, octaves, low, high),
+            TextureDescription::Marble {
+                scale,
+                octaves,
+                low,
+                high,
+            } => Texture::marble(scale, octaves, low, high),
+            TextureDescription::Worley { scale, near, far } => Texture::worley(scale, near, far),
+        };
+
+        Ok(textures.add_procedural(texture))
+    }
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct SphereDescription {
+    pub center: Vec3<f32>,
+    pub radius: f32,
+
+    /// Index into the scene's materials
+    pub material: usize,
+}
+
+/// Serializable description of a [`Scene`], stored as `.ron`, `.json` or `.toml`
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SceneFile {
+    pub camera: Camera,
+    #[serde(default)]
+    pub settings: RenderSettings,
+    #[serde(default)]
+    pub materials: Vec<MaterialDescription>,
+    #[serde(default)]
+    pub spheres: Vec<SphereDescription>,
+
+    /// `.obj`, `.gltf` or `.glb` files, relative to the scene file
+    #[serde(default)]
+    pub meshes: Vec<PathBuf>,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub textures: Vec<TextureDescription>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub environment: Option<EnvironmentDescription>,
+}
+
+fn extension(path: &Path) -> Option<&str> {
+    path.extension().and_then(OsStr::to_str)
+}
+
+impl SceneFile {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Box<dyn Error>> {
+        let path = path.as_ref();
+        let contents = fs::read_to_string(path)?;
+
+        let scene_file = match extension(path) {
+            Some("json") => serde_json::from_str(&contents)?,
+            Some("toml") => toml::from_str(&contents)?,
+            // Optional fields like the environment don't need to be wrapped in `Some`
+            _ => Options::default()
+                .with_default_extension(Extensions::IMPLICIT_SOME)
+                .from_str(&contents)?,
+        };
+
+        Ok(scene_file)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), Box<dyn Error>> {
+        let path = path.as_ref();
+
+        let contents = match extension(path) {
+            Some("json") => serde_json::to_string_pretty(self)?,
+            Some("toml") => toml::to_string_pretty(self)?,
+            _ => {
+                let config = PrettyConfig::new()
+                    .depth_limit(3)
+                    .extensions(Extensions::IMPLICIT_SOME);
+
+                ron::ser::to_string_pretty(self, config)?
+            }
+        };
+
+        fs::write(path, contents)?;
+
+        Ok(())
+    }
+
+    /// Describes a scene that was built in code. Triangle geometry can't be described, only
+    /// referenced by path, so it's left out
+    pub fn from_scene(scene: &Scene) -> Self {
+        if !scene.mesh.triangles.is_empty() {
+            eprintln!("Scene files don't store triangles, leaving out the mesh");
+        }
+
+        if scene.textures.textures.len() > 1 {
+            eprintln!("Scene files don't store images, leaving out the textures");
+        }
+
+        if scene.environment.is_some() {
+            eprintln!("Scene files don't store images, leaving out the environment map");
+        }
+
+        let mut materials: Vec<MaterialDescription> = Vec::new();
+
+        let spheres = scene
+            .spheres
+            .iter()
+            .map(|sphere| {
+                let material = MaterialDescription::from(sphere.material);
+
+                // Share identical materials between spheres
+                let material = match materials.iter().position(|&other| other == material) {
+                    Some(index) => index,
+                    None => {
+                        materials.push(material);
+                        materials.len() - 1
+                    }
+                };
+
+                SphereDescription {
+                    center: sphere.center,
+                    radius: sphere.radius,
+                    material,
+                }
+            })
+            .collect();
+
+        Self {
+            camera: scene.camera,
+            settings: scene.settings,
+            materials,
+            spheres,
+            meshes: Vec::new(),
+            textures: Vec::new(),
+            environment: None,
+        }
+    }
+
+    /// `textures` maps the scene's textures to their index in the atlas
+    fn convert_sphere(
+        &self,
+        sphere: SphereDescription,
+        textures: &[u32],
+    ) -> Result<Sphere, Box<dyn Error>> {
+        let material = self
+            .materials
+            .get(sphere.material)
+            .ok_or_else(|| format!("Sphere uses missing material {}", sphere.material))?;
+
+        let texture = match material.texture() {
+            Some(texture) => *textures
+                .get(texture)
+                .ok_or_else(|| format!("Material uses missing texture {texture}"))?,
+            None => 0,
+        };
+
+        Ok(Sphere {
+            center: sphere.center,
+            radius: sphere.radius,
+            material: Material::from(*material).with_texture(texture),
+            ..Default::default()
+        })
+    }
+
+    /// Builds the scene, loading meshes and textures relative to `directory`
+    pub fn into_scene(self, directory: &Path) -> Result<Scene, Box<dyn Error>> {
+        let mut textures = TextureAtlas::default();
+
+        let texture_indices: Vec<u32> = self
+            .textures
+            .iter()
+            .map(|texture| texture.load(directory, &mut textures))
+            .collect::<Result<_, _>>()?;
+
+        let spheres = self
+            .spheres
+            .iter()
+            .map(|&sphere| self.convert_sphere(sphere, &texture_indices))
+            .collect::<Result<_, _>>()?;
+
+        let mut mesh = MeshData::default();
+
+        for path in &self.meshes {
+            let path = directory.join(path);
+
+            match extension(&path) {
+                Some("gltf" | "glb") => mesh.extend(load_gltf(&path, &mut textures)?.0),
+                _ => mesh.extend(load_obj(&path, &mut textures)?),
+            }
+        }
+
+        let environment = match &self.environment {
+            Some(environment) => Some(EnvironmentMap {
+                texture: textures.load(directory.join(&environment.path))?,
+                rotation: environment.rotation,
+                intensity: environment.intensity,
+            }),
+            None => None,
+        };
+
+        Ok(Scene {
+            camera: self.camera,
+            settings: self.settings,
+            spheres,
+            mesh,
+            textures,
+            environment,
+        })
+    }
+}
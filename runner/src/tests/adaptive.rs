@@ -0,0 +1,32 @@
+//! Adaptive sampling should spend its budget unevenly, and never go outside of it
+
+use super::{render_accumulation, test_scene};
+use crate::{
+    adaptive::AdaptiveSampling,
+    render::{Backend, PassSettings},
+};
+use vek::Vec2;
+
+#[test]
+fn converged_pixels_stop_sampling() {
+    let screen_size = Vec2::new(48, 27);
+    let (data, seeds) = test_scene("glass.ron", screen_size, 64);
+
+    let settings = PassSettings {
+        tile_size: screen_size,
+        samples_per_pass: 8,
+        adaptive: Some(AdaptiveSampling {
+            noise_threshold: 0.05,
+            min_samples: 8,
+        }),
+    };
+    let accumulation = render_accumulation(Backend::Cpu, &data, &seeds, settings);
+
+    let samples: Vec<_> = accumulation.iter().map(|pixel| pixel.samples).collect();
+    assert!(samples.iter().all(|&samples| (8..=64).contains(&samples)));
+    assert!(samples.contains(&8), "No pixel converged early");
+    assert!(samples.contains(&64), "Every pixel converged early");
+
+    // Pixels only stop between passes
+    assert!(samples.iter().all(|samples| samples % 8 == 0));
+}
@@ -0,0 +1,122 @@
+//! The first hit passes of spheres placed in front of the camera should come out where and as
+//! what the scene put them
+
+use super::{render_with_aovs, single_tile, SEED};
+use crate::{
+    camera::Camera,
+    mesh::MeshData,
+    render::{sample_seeds, Backend, RenderData},
+    scene::Scene,
+    texture::TextureAtlas,
+};
+use shader::{AovAccumulation, Material, Sphere, NO_OBJECT};
+use vek::{Vec2, Vec3};
+
+/// Renders the spheres seen from `distance` along the z axis, sharply focused on the origin
+fn render_spheres(
+    spheres: Vec<Sphere>,
+    distance: f32,
+    screen_size: Vec2<u32>,
+) -> Vec<AovAccumulation> {
+    let mut scene = Scene::from_mesh(MeshData::default(), TextureAtlas::default());
+    scene.camera = Camera {
+        position: Vec3::new(0., 0., distance),
+        target: Vec3::zero(),
+        up: Vec3::unit_y(),
+        vertical_fov: 30_f32.to_radians(),
+        defocus_angle: 0.,
+        focus_distance: distance,
+    };
+    scene.settings.screen_size = screen_size;
+    scene.settings.amount_of_samples = 4;
+    scene.settings.max_depth = 4;
+    scene.spheres = spheres;
+
+    let mut data = RenderData::new(scene);
+    data.raytrace_settings.write_aovs = 1;
+    let seeds = sample_seeds(SEED, 4);
+
+    render_with_aovs(Backend::Cpu, &data, &seeds, single_tile(screen_size, 4)).1
+}
+
+#[test]
+fn single_sphere_passes() {
+    let screen_size = Vec2::new(32, 32);
+    let albedo = Vec3::new(0.8, 0.4, 0.2);
+    let aovs = render_spheres(
+        vec![Sphere {
+            center: Vec3::zero(),
+            radius: 1.,
+            material: Material::diffuse(albedo),
+            ..Default::default()
+        }],
+        5.,
+        screen_size,
+    );
+
+    // Straight ahead the camera rays hit the front of the sphere, four units away. The pixel and
+    // the samples within it are a little off center, about 0.08 units to a pixel there
+    let center = aovs[(16 * screen_size.x + 16) as usize];
+    assert_eq!(center.samples, 4);
+    assert_eq!(center.hits, 4);
+    assert!(
+        (center.average_depth() - 4.).abs() < 0.01,
+        "Depth {} in the center",
+        center.average_depth()
+    );
+    assert!(
+        (center.average_position() - Vec3::unit_z()).magnitude() < 0.1,
+        "Position {} in the center",
+        center.average_position()
+    );
+    assert!(
+        center.average_normal().dot(Vec3::unit_z()) > 0.99,
+        "Normal {} in the center",
+        center.average_normal()
+    );
+    assert!(
+        (center.average_albedo() - albedo).magnitude() < 1e-5,
+        "Albedo {} in the center",
+        center.average_albedo()
+    );
+    assert_eq!(center.object_id, 0);
+    assert_eq!(center.material_id, 0);
+
+    // The corners see past it
+    let corner = aovs[0];
+    assert_eq!(corner.hits, 0);
+    assert_eq!(corner.average_depth(), 0.);
+    assert_eq!(corner.average_normal(), Vec3::zero());
+    assert_eq!(corner.object_id, NO_OBJECT);
+    assert_eq!(corner.material_id, NO_OBJECT);
+}
+
+#[test]
+fn ids_follow_the_scene_order() {
+    let screen_size = Vec2::new(64, 32);
+    let red = Material::diffuse(Vec3::new(0.8, 0.1, 0.1));
+    let sphere = |x, material| Sphere {
+        center: Vec3::new(x, 0., 0.),
+        radius: 1.,
+        material,
+        ..Default::default()
+    };
+
+    // Listed right to left, the first and last share a material
+    let aovs = render_spheres(
+        vec![
+            sphere(2.5, red),
+            sphere(0., Material::metal(Vec3::one(), 0.)),
+            sphere(-2.5, red),
+        ],
+        10.,
+        screen_size,
+    );
+
+    // Ten units away the view is about 5.4 units to either side of the center
+    for (x, object_id, material_id) in [(47, 0, 0), (32, 1, 1), (17, 2, 0)] {
+        let pixel = aovs[(16 * screen_size.x + x) as usize];
+        assert_eq!(pixel.object_id, object_id, "Object at column {x}");
+        assert_eq!(pixel.material_id, material_id, "Material at column {x}");
+    }
+}
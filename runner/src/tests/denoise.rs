@@ -0,0 +1,24 @@
+//! The denoiser should bring a render with few samples closer to one with many
+
+use super::{clamp, render_scene, render_scene_with_aovs, rmse, SEED};
+use crate::{denoise::Denoiser, render::average};
+use vek::Vec2;
+
+#[test]
+fn denoising_reduces_error() {
+    let screen_size = Vec2::new(48, 48);
+
+    let reference = render_scene("cornell_box.ron", screen_size, 128, SEED, |_| {});
+    let reference = clamp(average(&reference));
+
+    let (accumulation, aovs) = render_scene_with_aovs("cornell_box.ron", screen_size, 8);
+    let raw = clamp(average(&accumulation));
+    let denoised = clamp(Denoiser::default().denoise(&accumulation, &aovs, screen_size));
+
+    let raw_error = rmse(&raw, &reference);
+    let denoised_error = rmse(&denoised, &reference);
+    assert!(
+        denoised_error < raw_error * 0.75,
+        "Denoised error {denoised_error} isn't well below the raw error {raw_error}"
+    );
+}
@@ -0,0 +1,109 @@
+//! Renders the example scenes on the cpu and compares them to reference images in
+//! `tests/golden`. After an intentional change to the output, regenerate the references with
+//! `UPDATE_GOLDEN=1 cargo test golden` and look over the new images before committing them
+
+use super::{render_scene, rmse, SEED};
+use crate::{
+    color::ColorPipeline,
+    output::{write_image, OutputFormat},
+    render::average,
+};
+use std::{env, fs, path::PathBuf};
+use vek::{Vec2, Vec3};
+
+/// Largest root mean square difference allowed, in display colors from zero to one. Leaves room
+/// for float math that differs a little between platforms
+const THRESHOLD: f32 = 0.02;
+
+fn reference_path(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/golden")
+        .join(format!("{name}.png"))
+}
+
+fn assert_golden(scene: &str, screen_size: Vec2<u32>, amount_of_samples: u32) {
+    let name = scene.trim_end_matches(".ron");
+    let pixels = average(&render_scene(
+        scene,
+        screen_size,
+        amount_of_samples,
+        SEED,
+        |_| {},
+    ));
+    let pipeline = ColorPipeline::default();
+    let path = reference_path(name);
+
+    if env::var_os("UPDATE_GOLDEN").is_some() {
+        fs::create_dir_all(path.parent().unwrap()).expect("Failed to create reference directory");
+        write_image(
+            &path,
+            OutputFormat::Png,
+            screen_size,
+            &pixels,
+            &pipeline,
+            &[],
+        )
+        .expect("Failed to write reference");
+        return;
+    }
+
+    let reference = image::open(&path)
+        .unwrap_or_else(|error| {
+            panic!(
+                "Failed to read {}, run with UPDATE_GOLDEN=1 to create it: {error}",
+                path.display()
+            )
+        })
+        .to_rgb32f();
+    assert_eq!(
+        Vec2::new(reference.width(), reference.height()),
+        screen_size,
+        "{name}: reference has a different size"
+    );
+
+    let reference: Vec<Vec3<f32>> = reference.pixels().map(|pixel| pixel.0.into()).collect();
+    let rendered: Vec<Vec3<f32>> = pipeline
+        .quantize(&pixels, u8::MAX as f32)
+        .into_iter()
+        .map(|pixel| pixel / u8::MAX as f32)
+        .collect();
+
+    let error = rmse(&rendered, &reference);
+    if error >= THRESHOLD {
+        let actual_path = env::temp_dir().join(format!("{name}.actual.png"));
+        write_image(
+            &actual_path,
+            OutputFormat::Png,
+            screen_size,
+            &pixels,
+            &pipeline,
+            &[],
+        )
+        .expect("Failed to write the rendered image");
+
+        panic!(
+            "{name}: rmse {error} against the reference, rendered image written to {}",
+            actual_path.display()
+        );
+    }
+}
+
+#[test]
+fn random_spheres() {
+    assert_golden("random_spheres.ron", Vec2::new(96, 48), 16);
+}
+
+#[test]
+fn cornell_box() {
+    assert_golden("cornell_box.ron", Vec2::new(48, 48), 128);
+}
+
+#[test]
+fn glass() {
+    assert_golden("glass.ron", Vec2::new(96, 54), 16);
+}
+
+#[test]
+fn metal() {
+    assert_golden("metal.ron", Vec2::new(96, 54), 16);
+}
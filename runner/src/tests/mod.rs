@@ -0,0 +1,161 @@
+//! Renders small versions of the example scenes, shared by the test suites
+
+mod adaptive;
+mod aov;
+mod denoise;
+mod golden;
+mod parity;
+mod progressive;
+mod roulette;
+mod sampler;
+
+use crate::{
+    render::{average, render, sample_seeds, Backend, PassSettings, RenderData},
+    scene::load_scene,
+};
+use shader::{Accumulation, AovAccumulation, RaytraceSettings};
+use std::{ops::ControlFlow, path::PathBuf, sync::atomic::AtomicBool};
+use vek::{Vec2, Vec3};
+
+/// Seed used by every test, the images only change when the renderer does
+const SEED: u64 = 1;
+
+fn scene_path(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("../scenes")
+        .join(name)
+}
+
+/// Loads one of the example scenes at a resolution and sample count small enough for a test,
+/// along with the seeds to render it with
+fn test_scene(
+    name: &str,
+    screen_size: Vec2<u32>,
+    amount_of_samples: u32,
+) -> (RenderData, Vec<u32>) {
+    let mut scene = load_scene(scene_path(name)).expect("Failed to load scene");
+    scene.settings.screen_size = screen_size;
+    scene.settings.amount_of_samples = amount_of_samples;
+    scene.settings.max_depth = scene.settings.max_depth.min(8);
+
+    let seeds = sample_seeds(SEED, amount_of_samples);
+
+    (RenderData::new(scene), seeds)
+}
+
+/// One tile over the whole image, without adaptive sampling
+fn single_tile(screen_size: Vec2<u32>, samples_per_pass: u32) -> PassSettings {
+    PassSettings {
+        tile_size: screen_size,
+        samples_per_pass,
+        adaptive: None,
+    }
+}
+
+/// Renders all samples from scratch, along with the first hits when the settings ask for them
+fn render_with_aovs(
+    backend: Backend,
+    data: &RenderData,
+    seeds: &[u32],
+    settings: PassSettings,
+) -> (Vec<Accumulation>, Vec<AovAccumulation>) {
+    let pixels = data.raytrace_settings.screen_size.product() as usize;
+    let mut accumulation = vec![Accumulation::default(); pixels];
+    let mut aovs = if data.raytrace_settings.write_aovs != 0 {
+        vec![AovAccumulation::default(); pixels]
+    } else {
+        Vec::new()
+    };
+
+    pollster::block_on(render(
+        backend,
+        data,
+        seeds,
+        0,
+        settings,
+        &mut accumulation,
+        &mut aovs,
+        &AtomicBool::new(false),
+        |_, _, _| ControlFlow::Continue(()),
+    ));
+
+    (accumulation, aovs)
+}
+
+/// Renders all samples from scratch
+fn render_accumulation(
+    backend: Backend,
+    data: &RenderData,
+    seeds: &[u32],
+    settings: PassSettings,
+) -> Vec<Accumulation> {
+    render_with_aovs(backend, data, seeds, settings).0
+}
+
+/// Renders all samples from scratch, returns the averaged linear colors
+fn render_image(
+    backend: Backend,
+    data: &RenderData,
+    seeds: &[u32],
+    settings: PassSettings,
+) -> Vec<Vec3<f32>> {
+    average(&render_accumulation(backend, data, seeds, settings))
+}
+
+/// Renders one of the example scenes on the cpu in a single pass, with the sample seeds drawn from
+/// `seed` and the settings changed by `adjust` first
+fn render_scene(
+    name: &str,
+    screen_size: Vec2<u32>,
+    amount_of_samples: u32,
+    seed: u64,
+    adjust: impl FnOnce(&mut RaytraceSettings),
+) -> Vec<Accumulation> {
+    let (mut data, _) = test_scene(name, screen_size, amount_of_samples);
+    adjust(&mut data.raytrace_settings);
+
+    let seeds = sample_seeds(seed, amount_of_samples);
+    let settings = single_tile(screen_size, amount_of_samples);
+
+    render_accumulation(Backend::Cpu, &data, &seeds, settings)
+}
+
+/// [`render_scene`] with the first hits written as well
+fn render_scene_with_aovs(
+    name: &str,
+    screen_size: Vec2<u32>,
+    amount_of_samples: u32,
+) -> (Vec<Accumulation>, Vec<AovAccumulation>) {
+    let (mut data, seeds) = test_scene(name, screen_size, amount_of_samples);
+    data.raytrace_settings.write_aovs = 1;
+
+    let settings = single_tile(screen_size, amount_of_samples);
+
+    render_with_aovs(Backend::Cpu, &data, &seeds, settings)
+}
+
+/// Clamped like a display would, so single fireflies, or paths that split up over tiny
+/// differences in float math, don't dominate the error
+fn clamp(pixels: Vec<Vec3<f32>>) -> Vec<Vec3<f32>> {
+    pixels
+        .into_iter()
+        .map(|pixel| pixel.map(|c| c.clamp(0., 1.)))
+        .collect()
+}
+
+/// Root mean square difference over all channels
+fn rmse(a: &[Vec3<f32>], b: &[Vec3<f32>]) -> f32 {
+    assert_eq!(a.len(), b.len(), "Images differ in size");
+
+    let sum: f64 = a
+        .iter()
+        .zip(b)
+        .map(|(&a, &b)| (a - b).map(|c| c as f64 * c as f64).sum())
+        .sum();
+
+    (sum / (3 * a.len()) as f64).sqrt() as f32
+}
+
+fn mean(pixels: &[Vec3<f32>]) -> Vec3<f32> {
+    pixels.iter().copied().sum::<Vec3<f32>>() / pixels.len() as f32
+}
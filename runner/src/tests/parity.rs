@@ -0,0 +1,71 @@
+//! The shader runs on the gpu and natively for the cpu backend, both should draw the same image.
+//! Paths may still split up over tiny differences in float math, so the images are compared
+//! statistically rather than exactly. They need an adapter to render on, so they only run when
+//! asked for
+
+use super::{clamp, mean, render_with_aovs, rmse, single_tile, test_scene};
+use crate::render::{average, Backend, PassSettings};
+use vek::Vec2;
+
+fn assert_parity(name: &str, screen_size: Vec2<u32>, amount_of_samples: u32) {
+    let (mut data, seeds) = test_scene(name, screen_size, amount_of_samples);
+    data.raytrace_settings.write_aovs = 1;
+
+    let cpu_settings = single_tile(screen_size, amount_of_samples);
+    let (cpu, cpu_aovs) = render_with_aovs(Backend::Cpu, &data, &seeds, cpu_settings);
+    let cpu = clamp(average(&cpu));
+
+    // Small tiles and an uneven split of the samples cover the dispatch bookkeeping as well
+    let gpu_settings = PassSettings {
+        tile_size: Vec2::new(24, 16),
+        samples_per_pass: 3,
+        adaptive: None,
+    };
+    let (gpu, gpu_aovs) = render_with_aovs(Backend::Gpu, &data, &seeds, gpu_settings);
+    let gpu = clamp(average(&gpu));
+
+    // Layout mismatches or broken math show up as large errors or a shifted average
+    let error = rmse(&cpu, &gpu);
+    assert!(error < 0.05, "{name}: rmse {error} between cpu and gpu");
+
+    let mean_difference = (mean(&cpu) - mean(&gpu)).map(f32::abs).reduce_partial_max();
+    assert!(
+        mean_difference < 0.01,
+        "{name}: average color differs by {mean_difference}"
+    );
+
+    let diverged = cpu
+        .iter()
+        .zip(&gpu)
+        .filter(|(&cpu, &gpu)| (cpu - gpu).map(f32::abs).reduce_partial_max() > 0.1)
+        .count();
+    assert!(
+        diverged * 20 < cpu.len(),
+        "{name}: {diverged} of {} pixels differ",
+        cpu.len()
+    );
+
+    // The first sample's camera ray is the same on both, only grazing hits may end up elsewhere
+    let different_ids = cpu_aovs
+        .iter()
+        .zip(&gpu_aovs)
+        .filter(|(cpu, gpu)| cpu.object_id != gpu.object_id || cpu.material_id != gpu.material_id)
+        .count();
+    assert!(
+        different_ids * 100 < cpu_aovs.len(),
+        "{name}: {different_ids} of {} pixels hit a different object or material",
+        cpu_aovs.len()
+    );
+}
+
+#[test]
+#[ignore = "needs a gpu adapter"]
+fn random_spheres() {
+    assert_parity("random_spheres.ron", Vec2::new(64, 32), 8);
+}
+
+#[test]
+#[ignore = "needs a gpu adapter"]
+fn cornell_box() {
+    assert_parity("cornell_box.ron", Vec2::new(40, 40), 8);
+}
@@ -0,0 +1,130 @@
+//! Renders stopped partway and continued later should end up exactly where an uninterrupted
+//! render does
+
+use super::{render_accumulation, render_image, single_tile, test_scene};
+use crate::{
+    checkpoint::{scene_hash, Checkpoint},
+    render::{average, render, Backend},
+};
+use shader::{Accumulation, AovAccumulation};
+use std::{env, fs, ops::ControlFlow, sync::atomic::AtomicBool};
+use vek::Vec2;
+
+#[test]
+fn continued_render_matches() {
+    let screen_size = Vec2::new(48, 24);
+    let (data, seeds) = test_scene("random_spheres.ron", screen_size, 8);
+
+    let settings = single_tile(screen_size, 3);
+    let uninterrupted = render_image(Backend::Cpu, &data, &seeds, settings);
+
+    // Stop after the first pass, then pick up again at the first seed that's left
+    let mut accumulation = vec![Accumulation::default(); screen_size.product() as usize];
+    pollster::block_on(render(
+        Backend::Cpu,
+        &data,
+        &seeds,
+        0,
+        settings,
+        &mut accumulation,
+        &mut [],
+        &AtomicBool::new(false),
+        |_, _, _| ControlFlow::Break(()),
+    ));
+    assert!(accumulation.iter().all(|pixel| pixel.samples == 3));
+
+    pollster::block_on(render(
+        Backend::Cpu,
+        &data,
+        &seeds,
+        3,
+        settings,
+        &mut accumulation,
+        &mut [],
+        &AtomicBool::new(false),
+        |_, _, _| ControlFlow::Continue(()),
+    ));
+    assert!(accumulation.iter().all(|pixel| pixel.samples == 8));
+
+    assert!(average(&accumulation) == uninterrupted);
+}
+
+#[test]
+fn pass_stopped_between_tiles_is_finished() {
+    let screen_size = Vec2::new(48, 24);
+    let (data, seeds) = test_scene("random_spheres.ron", screen_size, 8);
+
+    let settings = single_tile(screen_size, 3);
+    let uninterrupted = render_image(Backend::Cpu, &data, &seeds, settings);
+
+    // The second pass of three samples only got through the upper half before stopping
+    let first_pass = render_accumulation(Backend::Cpu, &data, &seeds[..3], settings);
+    let second_pass = render_accumulation(Backend::Cpu, &data, &seeds[..6], settings);
+    let half = (screen_size.product() / 2) as usize;
+    let mut accumulation = [&second_pass[..half], &first_pass[half..]].concat();
+
+    // Continuing after the first pass, the upper half only takes the samples it lacks
+    pollster::block_on(render(
+        Backend::Cpu,
+        &data,
+        &seeds,
+        3,
+        settings,
+        &mut accumulation,
+        &mut [],
+        &AtomicBool::new(false),
+        |_, _, _| ControlFlow::Continue(()),
+    ));
+    assert!(accumulation.iter().all(|pixel| pixel.samples == 8));
+
+    assert!(average(&accumulation) == uninterrupted);
+}
+
+#[test]
+fn checkpoint_round_trip() {
+    let screen_size = Vec2::new(16, 8);
+    let (mut data, seeds) = test_scene("random_spheres.ron", screen_size, 2);
+    data.raytrace_settings.write_aovs = 1;
+
+    let pixels = screen_size.product() as usize;
+    let mut accumulation = vec![Accumulation::default(); pixels];
+    let mut aovs = vec![AovAccumulation::default(); pixels];
+    pollster::block_on(render(
+        Backend::Cpu,
+        &data,
+        &seeds,
+        0,
+        single_tile(screen_size, 2),
+        &mut accumulation,
+        &mut aovs,
+        &AtomicBool::new(false),
+        |_, _, _| ControlFlow::Continue(()),
+    ));
+
+    let checkpoint = Checkpoint {
+        scene_hash: scene_hash(&data),
+        seed: 1,
+        samples: 2,
+        screen_size,
+        accumulation,
+        aovs,
+    };
+    let path = env::temp_dir().join("raytracer_checkpoint_round_trip.ckpt");
+    checkpoint.save(&path).expect("Failed to write checkpoint");
+
+    // More samples or other passes can be added to a checkpoint, other settings make it a
+    // different scene
+    let (more_samples, _) = test_scene("random_spheres.ron", screen_size, 4);
+    let (larger, _) = test_scene("random_spheres.ron", screen_size * 2, 2);
+    assert!(Checkpoint::load(&path, scene_hash(&larger)).is_err());
+
+    let loaded =
+        Checkpoint::load(&path, scene_hash(&more_samples)).expect("Failed to load checkpoint");
+    fs::remove_file(path).ok();
+
+    assert_eq!(loaded.seed, checkpoint.seed);
+    assert_eq!(loaded.samples, checkpoint.samples);
+    assert!(average(&loaded.accumulation) == average(&checkpoint.accumulation));
+    let bytes = |aovs: &[AovAccumulation]| bytemuck::cast_slice::<_, u8>(aovs).to_vec();
+    assert!(bytes(&loaded.aovs) == bytes(&checkpoint.aovs));
+}
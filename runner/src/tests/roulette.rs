@@ -0,0 +1,39 @@
+//! Russian roulette should shorten the paths without changing what they converge to
+
+use super::{mean, render_scene, SEED};
+use crate::render::{average, average_path_length};
+use vek::Vec2;
+
+#[test]
+fn russian_roulette_is_unbiased() {
+    let render = |russian_roulette_depth| {
+        render_scene(
+            "cornell_box.ron",
+            Vec2::new(32, 32),
+            128,
+            SEED,
+            |settings| settings.russian_roulette_depth = russian_roulette_depth,
+        )
+    };
+
+    // The test scenes are limited to 8 bounces, so a roulette depth of 8 never ends a path early
+    let full = render(8);
+    let roulette = render(1);
+
+    let full_length = average_path_length(&full);
+    let roulette_length = average_path_length(&roulette);
+    assert!(
+        roulette_length < full_length * 0.9,
+        "Paths average {roulette_length} rays with Russian roulette, {full_length} without"
+    );
+
+    let full_mean = mean(&average(&full));
+    let roulette_mean = mean(&average(&roulette));
+    let difference = ((roulette_mean - full_mean) / full_mean)
+        .map(f32::abs)
+        .reduce_partial_max();
+    assert!(
+        difference < 0.05,
+        "Mean brightness {roulette_mean} with Russian roulette differs from {full_mean} by {difference}"
+    );
+}
@@ -0,0 +1,63 @@
+//! The low discrepancy samplers should get closer to the converged image than independent random
+//! numbers at the same sample count
+
+use super::{clamp, render_scene, rmse, SEED};
+use crate::{blue_noise::blue_noise_mask, render::average};
+use shader::{SamplerKind, BLUE_NOISE_SIZE};
+use vek::{Vec2, Vec3};
+
+fn render_with(sampler: SamplerKind, amount_of_samples: u32, seed: u64) -> Vec<Vec3<f32>> {
+    let accumulation = render_scene(
+        "random_spheres.ron",
+        Vec2::new(32, 16),
+        amount_of_samples,
+        seed,
+        |settings| settings.sampler = sampler,
+    );
+
+    clamp(average(&accumulation))
+}
+
+#[test]
+fn low_discrepancy_converges_faster() {
+    // Rendered with its own seed, so it shares no samples with any of the renders it judges
+    let reference = render_with(SamplerKind::Independent, 512, SEED + 1);
+    let independent = rmse(&render_with(SamplerKind::Independent, 16, SEED), &reference);
+
+    for sampler in [SamplerKind::Sobol, SamplerKind::BlueNoise] {
+        let error = rmse(&render_with(sampler, 16, SEED), &reference);
+        assert!(
+            error < independent,
+            "Error {error} isn't below the independent sampler's {independent}"
+        );
+    }
+}
+
+#[test]
+fn blue_noise_mask_spreads_values() {
+    let mask = blue_noise_mask();
+    let size = BLUE_NOISE_SIZE as usize;
+
+    let mut ranks: Vec<usize> = mask
+        .iter()
+        .map(|value| (value * mask.len() as f32) as usize)
+        .collect();
+    ranks.sort_unstable();
+    assert!(
+        ranks.iter().enumerate().all(|(i, &rank)| rank == i),
+        "Every value should show up once"
+    );
+
+    // Independent values differ from their neighbours by a third on average
+    let difference = (0..mask.len())
+        .map(|i| {
+            let neighbour = (i / size) * size + (i + 1) % size;
+            (mask[i] - mask[neighbour]).abs()
+        })
+        .sum::<f32>()
+        / mask.len() as f32;
+    assert!(
+        difference > 0.4,
+        "Neighbours differ by {difference} on average"
+    );
+}
@@ -0,0 +1,75 @@
+use crate::color::srgb_to_linear;
+use image::{DynamicImage, ImageResult};
+use shader::Texture;
+use std::path::Path;
+use vek::{Vec2, Vec3};
+
+/// Host side textures, packed one after another into a single buffer of linear colors
+#[derive(Clone)]
+pub struct TextureAtlas {
+    pub textures: Vec<Texture>,
+    pub texels: Vec<Vec3<f32>>,
+}
+
+impl Default for TextureAtlas {
+    /// Starts out with a single white texel, used by materials without a texture
+    fn default() -> Self {
+        Self {
+            textures: vec![Texture::image(0, Vec2::one())],
+            texels: vec![Vec3::one()],
+        }
+    }
+}
+
+impl TextureAtlas {
+    /// Adds an image stored row by row from the top, returns its index
+    pub fn add(&mut self, size: Vec2<u32>, texels: impl IntoIterator<Item = Vec3<f32>>) -> u32 {
+        let offset = self.texels.len() as u32;
+
+        self.texels.extend(texels);
+        assert_eq!(
+            self.texels.len() as u32 - offset,
+            size.product(),
+            "Texel count doesn't match the texture size"
+        );
+
+        self.add_procedural(Texture::image(offset, size))
+    }
+
+    /// Adds a texture that doesn't need texels, returns its index
+    pub fn add_procedural(&mut self, texture: Texture) -> u32 {
+        self.textures.push(texture);
+        self.textures.len() as u32 - 1
+    }
+
+    /// Adds a decoded image, 8 and 16 bit images are treated as sRGB and floating point ones as
+    /// linear
+    pub fn add_image(&mut self, image: DynamicImage) -> u32 {
+        let size = Vec2::new(image.width(), image.height());
+
+        let is_linear = matches!(
+            image,
+            DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_)
+        );
+
+        let texels = image.into_rgb32f().into_raw();
+        let texels = texels.chunks_exact(3).map(|texel| {
+            let color = Vec3::from_slice(texel);
+
+            if is_linear {
+                color
+            } else {
+                color.map(srgb_to_linear)
+            }
+        });
+
+        self.add(size, texels)
+    }
+
+    /// Loads a PNG, JPEG, Radiance HDR or OpenEXR image, returns its index
+    pub fn load(&mut self, path: impl AsRef<Path>) -> ImageResult<u32> {
+        let image = image::open(path)?;
+
+        Ok(self.add_image(image))
+    }
+}
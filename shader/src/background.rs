@@ -0,0 +1,39 @@
+use bytemuck::{Pod, Zeroable};
+use vek::Vec3;
+
+use crate::environment::Environment;
+
+/// What rays that don't hit anything see
+#[derive(Clone, Copy, Default)]
+#[repr(u32)]
+pub enum Background {
+    /// White to blue gradient
+    #[default]
+    Sky,
+
+    /// No light from the background, the scene is only lit by emissive materials
+    Black,
+
+    /// Equirectangular image, see [`EnvironmentSettings`]
+    ///
+    /// [`EnvironmentSettings`]: crate::EnvironmentSettings
+    Environment,
+}
+
+unsafe impl Zeroable for Background {}
+unsafe impl Pod for Background {}
+
+impl Background {
+    pub fn color(self, direction: Vec3<f32>, environment: Environment) -> Vec3<f32> {
+        match self {
+            Background::Sky => {
+                let unit_direction = direction.normalized();
+                let a = (unit_direction.y + 1.) / 2.;
+
+                Vec3::broadcast(1. - a) + a * Vec3::new(0.5, 0.7, 1.)
+            }
+            Background::Black => Vec3::zero(),
+            Background::Environment => environment.radiance(direction),
+        }
+    }
+}
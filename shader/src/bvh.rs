@@ -1,15 +1,17 @@
 use core::mem::swap;
 
+use bytemuck::{Pod, Zeroable};
 use spirv_std::num_traits::Float;
 use vek::Vec3;
 
 use crate::{
     data::{Range, RayHit},
     ray::Ray,
-    traits::Raycastable,
+    traits::{Primitives, Raycastable},
 };
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Zeroable, Pod)]
+#[repr(C)]
 pub struct Aabb {
     pub axes: Vec3<Range>,
 }
@@ -35,81 +37,153 @@ impl Aabb {
         }
     }
 
-    pub fn raycast(self, ray: Ray, range: Range) -> bool {
-        let mut range = range;
+    pub fn centroid(self) -> Vec3<f32> {
+        Vec3::new(
+            self.axes.x.center(),
+            self.axes.y.center(),
+            self.axes.z.center(),
+        )
+    }
 
-        for axis in 0..3 {
-            let inverse_direction = 1. / ray.direction[axis];
-            let origin = ray.origin[axis];
+    pub fn surface_area(self) -> f32 {
+        let x = self.axes.x.size();
+        let y = self.axes.y.size();
+        let z = self.axes.z.size();
 
-            let mut t0 = (self.axes[axis].min - origin) * inverse_direction;
-            let mut t1 = (self.axes[axis].max - origin) * inverse_direction;
+        2. * (x * y + y * z + z * x)
+    }
 
-            if inverse_direction < 0. {
-                swap(&mut t0, &mut t1);
-            }
+    pub fn raycast(self, ray: Ray, range: Range) -> bool {
+        // Axes are clipped one by one, indexing vectors dynamically doesn't compile to spirv
+        let range = clip_axis(range, self.axes.x, ray.origin.x, ray.direction.x);
+        let range = clip_axis(range, self.axes.y, ray.origin.y, ray.direction.y);
+        let range = clip_axis(range, self.axes.z, ray.origin.z, ray.direction.z);
 
-            range.min = Float::max(t0, range.min);
-            range.max = Float::min(t1, range.max);
+        range.max > range.min
+    }
+}
 
-            if range.max <= range.min {
-                return false;
-            }
-        }
+fn clip_axis(range: Range, axis: Range, origin: f32, direction: f32) -> Range {
+    let inverse_direction = 1. / direction;
 
-        true
+    let mut t0 = (axis.min - origin) * inverse_direction;
+    let mut t1 = (axis.max - origin) * inverse_direction;
+
+    if inverse_direction < 0. {
+        swap(&mut t0, &mut t1);
     }
+
+    Range::new(Float::max(t0, range.min), Float::min(t1, range.max))
 }
 
-pub struct BvhIndex(usize);
+pub const BVH_MAX_DEPTH: usize = 32;
+
+#[derive(Clone, Copy, Zeroable, Pod)]
+#[repr(C)]
+pub struct BvhNode {
+    pub bounding_box: Aabb,
+
+    /// Index of the left child for interior nodes, the right child follows it.
+    /// Index of the first primitive for leaves
+    pub left_or_first: u32,
+
+    /// Amount of primitives in a leaf, zero for interior nodes
+    pub amount: u32,
+}
 
-impl BvhIndex {
-    pub fn left(self) -> Self {
-        Self((2 * self.0) + 1)
+impl BvhNode {
+    pub fn interior(bounding_box: Aabb, left: u32) -> Self {
+        Self {
+            bounding_box,
+            left_or_first: left,
+            amount: 0,
+        }
     }
 
-    pub fn right(self) -> Self {
-        Self((2 * self.0) + 2)
+    pub fn leaf(bounding_box: Aabb, first: u32, amount: u32) -> Self {
+        Self {
+            bounding_box,
+            left_or_first: first,
+            amount,
+        }
     }
-}
 
-#[derive(Clone, Copy)]
-pub struct BvhNode<T: Copy> {
-    bounding_box: Aabb,
-    value: T,
+    pub fn is_leaf(self) -> bool {
+        self.amount > 0
+    }
 }
 
+/// Bvh over `amount` primitives, without any the nodes aren't looked at
 #[derive(Clone, Copy)]
-pub struct Bvh<'a, T: Copy> {
-    nodes: &'a [BvhNode<T>],
+pub struct Bvh<'a, P: Primitives> {
+    nodes: &'a [BvhNode],
+    primitives: P,
+    amount: u32,
 }
 
-impl<'a, T: Copy> Bvh<'a, T> {
-    pub fn root(self) -> BvhIndex {
-        BvhIndex(0)
-    }
-
-    pub fn contains(self, index: BvhIndex) -> bool {
-        index.0 < self.nodes.len()
+impl<'a, P: Primitives> Bvh<'a, P> {
+    pub fn new(nodes: &'a [BvhNode], primitives: P, amount: u32) -> Self {
+        Self {
+            nodes,
+            primitives,
+            amount,
+        }
     }
 
-    pub fn at(self, index: BvhIndex) -> BvhNode<T> {
-        self.nodes[index.0]
+    pub fn amount(self) -> u32 {
+        self.amount
     }
 }
 
-impl<'a, T: Raycastable + Copy> Raycastable for Bvh<'a, T> {
+impl<'a, P: Primitives> Raycastable for Bvh<'a, P> {
     fn raycast(self, ray: Ray, range: Range) -> RayHit {
-        if self.nodes.is_empty() {
-            return RayHit::none();
-        }
+        let mut closest_hit = RayHit::none();
+        let mut closest_distance = range.max;
 
-        let mut current = self.root();
+        if self.amount == 0 {
+            return closest_hit;
+        }
 
-        if self.contains(current.left()) {
-            // let ray_hit = self.at(current.left()).raycast(ray, )
+        // Every interior node pops itself and pushes two children, so the stack never holds more
+        // than one pending node per level plus the current one
+        let mut stack = [0u32; BVH_MAX_DEPTH + 1];
+        let mut stack_size = 1;
+
+        while stack_size > 0 {
+            stack_size -= 1;
+            let node = self.nodes[stack[stack_size] as usize];
+
+            if node
+                .bounding_box
+                .raycast(ray, Range::new(range.min, closest_distance))
+            {
+                if node.is_leaf() {
+                    for i in node.left_or_first..(node.left_or_first + node.amount) {
+                        let ray_hit = self.primitives.raycast_primitive(
+                            i as usize,
+                            ray,
+                            Range {
+                                min: range.min,
+                                max: closest_distance,
+                            },
+                        );
+
+                        if ray_hit.did_hit {
+                            closest_distance = ray_hit.distance;
+                            closest_hit = RayHit {
+                                object_id: i,
+                                ..ray_hit
+                            };
+                        }
+                    }
+                } else {
+                    stack[stack_size] = node.left_or_first + 1;
+                    stack[stack_size + 1] = node.left_or_first;
+                    stack_size += 2;
+                }
+            }
         }
 
-        RayHit::none()
+        closest_hit
     }
 }
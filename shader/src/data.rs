@@ -1,9 +1,11 @@
+use bytemuck::{Pod, Zeroable};
 use spirv_std::num_traits::Float;
-use vek::Vec3;
+use vek::{Vec2, Vec3};
 
 use crate::{material::Material, ray::Ray};
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Zeroable, Pod)]
+#[repr(C)]
 pub struct Range {
     pub min: f32,
     pub max: f32,
@@ -21,6 +23,14 @@ impl Range {
         Self::new(Float::min(a.min, b.min), Float::max(a.max, b.max))
     }
 
+    pub fn size(self) -> f32 {
+        self.max - self.min
+    }
+
+    pub fn center(self) -> f32 {
+        (self.min + self.max) / 2.
+    }
+
     pub fn contains(self, value: f32) -> bool {
         value >= self.min && value < self.max
     }
@@ -59,8 +69,17 @@ pub struct RayHit {
     /// Normal, unit length
     pub normal: Vec3<f32>,
 
+    /// Texture coordinates at the hit point
+    pub uv: Vec2<f32>,
+
     /// The material of the hit shape
     pub material: Material,
+
+    /// Index of the hit sphere, or the amount of spheres plus the index of the hit triangle
+    pub object_id: u32,
+
+    /// The hit shape's id, numbered in scene order rather than buffer order
+    pub scene_id: u32,
 }
 
 impl RayHit {
@@ -79,6 +98,10 @@ pub struct ScatterResult {
 
     /// The color produced from scattering
     pub attenuation: Vec3<f32>,
+
+    /// Probability density of the scattered direction, zero for specular scattering which light
+    /// sampling can't produce
+    pub pdf: f32,
 }
 
 impl ScatterResult {
@@ -0,0 +1,186 @@
+use core::f32::consts::PI;
+
+use bytemuck::{Pod, Zeroable};
+use spirv_std::num_traits::Float;
+use vek::{Vec2, Vec3};
+
+use crate::{sampler::Sampler, texture::Textures};
+
+/// Equirectangular environment map, used when the background is [`Background::Environment`]
+///
+/// [`Background::Environment`]: crate::Background::Environment
+#[derive(Clone, Copy, Default, Zeroable, Pod)]
+#[repr(C)]
+pub struct EnvironmentSettings {
+    /// Index of the image in the textures
+    pub texture: u32,
+
+    /// Rotation around the y axis, in radians
+    pub rotation: f32,
+
+    /// Multiplier for the radiance of the map
+    pub intensity: f32,
+}
+
+#[derive(Clone, Copy, Default)]
+pub struct EnvironmentSample {
+    /// Direction towards the sampled texel, unit length
+    pub direction: Vec3<f32>,
+
+    /// Probability density of the direction, over solid angle
+    pub pdf: f32,
+}
+
+/// An environment map together with the distribution used to importance sample it
+#[derive(Clone, Copy)]
+pub struct Environment<'a> {
+    settings: EnvironmentSettings,
+    textures: Textures<'a>,
+
+    /// Cumulative distribution over the rows, followed by one over the columns of every row, each
+    /// starting at zero and ending at one
+    distribution: &'a [f32],
+}
+
+/// Index of the interval of the cumulative distribution `cdf[start..=start + size]` that contains
+/// `value`
+fn find_interval(cdf: &[f32], start: usize, size: u32, value: f32) -> u32 {
+    let mut low = 0;
+    let mut high = size;
+
+    while high - low > 1 {
+        let middle = (low + high) / 2;
+
+        if cdf[start + middle as usize] <= value {
+            low = middle;
+        } else {
+            high = middle;
+        }
+    }
+
+    low
+}
+
+impl<'a> Environment<'a> {
+    pub fn new(
+        settings: EnvironmentSettings,
+        textures: Textures<'a>,
+        distribution: &'a [f32],
+    ) -> Self {
+        Self {
+            settings,
+            textures,
+            distribution,
+        }
+    }
+
+    fn size(self) -> Vec2<u32> {
+        self.textures.size(self.settings.texture)
+    }
+
+    /// Where on the map a direction points, v goes from the top down
+    fn direction_to_uv(self, direction: Vec3<f32>) -> Vec2<f32> {
+        let direction = direction.normalized();
+
+        let u = (Float::atan2(direction.x, -direction.z) + PI - self.settings.rotation) / (2. * PI);
+        let v = Float::acos(Float::max(Float::min(direction.y, 1.), -1.)) / PI;
+
+        Vec2::new(u - Float::floor(u), v)
+    }
+
+    fn uv_to_direction(self, uv: Vec2<f32>) -> Vec3<f32> {
+        let phi = 2. * PI * uv.x - PI + self.settings.rotation;
+        let theta = PI * uv.y;
+
+        let sin_theta = Float::sin(theta);
+
+        Vec3::new(
+            sin_theta * Float::sin(phi),
+            Float::cos(theta),
+            -sin_theta * Float::cos(phi),
+        )
+    }
+
+    pub fn radiance(self, direction: Vec3<f32>) -> Vec3<f32> {
+        let uv = self.direction_to_uv(direction);
+
+        self.textures.sample(self.settings.texture, uv, direction) * self.settings.intensity
+    }
+
+    /// Offset of the cumulative distribution of a row's columns
+    fn row_start(self, row: u32) -> usize {
+        let size = self.size();
+
+        (size.y + 1 + row * (size.x + 1)) as usize
+    }
+
+    /// Probability of picking a texel, out of all texels
+    fn texel_probability(self, column: u32, row: u32) -> f32 {
+        let row_start = self.row_start(row);
+
+        let row_probability = self.distribution[row as usize + 1] - self.distribution[row as usize];
+        let column_probability = self.distribution[row_start + column as usize + 1]
+            - self.distribution[row_start + column as usize];
+
+        row_probability * column_probability
+    }
+
+    /// Converts a density over the map to one over solid angle
+    fn solid_angle_pdf(self, texel_probability: f32, v: f32) -> f32 {
+        let sin_theta = Float::sin(PI * v);
+        if sin_theta <= 0. {
+            return 0.;
+        }
+
+        let size = self.size();
+        let uv_pdf = texel_probability * (size.x * size.y) as f32;
+
+        uv_pdf / (2. * PI * PI * sin_theta)
+    }
+
+    /// Picks a direction with a probability proportional to the brightness of the map there
+    pub fn sample(self, sampler: &mut Sampler) -> EnvironmentSample {
+        let size = self.size();
+        let value = sampler.gen_vec2();
+
+        let row_value = value.x;
+        let row = find_interval(self.distribution, 0, size.y, row_value);
+
+        let row_start = self.row_start(row);
+        let column_value = value.y;
+        let column = find_interval(self.distribution, row_start, size.x, column_value);
+
+        // Place the sample inside the texel according to where the values fell in its interval
+        let row_low = self.distribution[row as usize];
+        let row_high = self.distribution[row as usize + 1];
+        let column_low = self.distribution[row_start + column as usize];
+        let column_high = self.distribution[row_start + column as usize + 1];
+
+        let row_offset = (row_value - row_low) / Float::max(row_high - row_low, 1e-8);
+        let column_offset =
+            (column_value - column_low) / Float::max(column_high - column_low, 1e-8);
+
+        let uv = Vec2::new(
+            (column as f32 + Float::min(column_offset, 1.)) / size.x as f32,
+            (row as f32 + Float::min(row_offset, 1.)) / size.y as f32,
+        );
+
+        let texel_probability = (row_high - row_low) * (column_high - column_low);
+
+        EnvironmentSample {
+            direction: self.uv_to_direction(uv),
+            pdf: self.solid_angle_pdf(texel_probability, uv.y),
+        }
+    }
+
+    /// Probability density of [`Environment::sample`] choosing `direction`
+    pub fn pdf(self, direction: Vec3<f32>) -> f32 {
+        let size = self.size();
+        let uv = self.direction_to_uv(direction);
+
+        let column = Float::min(uv.x * size.x as f32, size.x as f32 - 1.) as u32;
+        let row = Float::min(uv.y * size.y as f32, size.y as f32 - 1.) as u32;
+
+        self.solid_angle_pdf(self.texel_probability(column, row), uv.y)
+    }
+}
@@ -1,32 +1,265 @@
 #![no_std]
 
+mod background;
 mod bvh;
 mod data;
+mod environment;
+mod light;
 mod material;
+mod procedural;
 mod rand;
 mod ray;
+mod sampler;
 mod sphere;
+mod texture;
 mod traits;
+mod triangle;
+mod world;
 
+use bvh::Bvh;
 use bytemuck::{Pod, Zeroable};
 use data::{Range, RayHit};
-use rand::Rand;
+use environment::Environment;
+use light::{power_heuristic, Lights, ENVIRONMENT_ID};
 use ray::Ray;
+use sampler::Sampler;
 use spirv_std::{glam, num_traits::Float, spirv};
+use texture::Textures;
 use traits::Raycastable;
+use triangle::Mesh;
 use vek::{Vec2, Vec3};
+use world::World;
 
+pub use background::Background;
+pub use bvh::{Aabb, BvhNode, BVH_MAX_DEPTH};
+pub use environment::EnvironmentSettings;
 pub use glam::UVec3;
 pub use material::{Material, Reflection};
+pub use sampler::{SamplerKind, BLUE_NOISE_SIZE};
 pub use sphere::Sphere;
+pub use texture::{Texture, TextureKind};
+pub use traits::Bounded;
+pub use triangle::{Triangle, Vertex};
 
 #[derive(Clone, Copy, Zeroable, Pod)]
 #[repr(C)]
 pub struct RaytraceSettings {
     pub viewport: Viewport,
     pub screen_size: Vec2<u32>,
+
+    /// Samples the render aims for, the shader takes as many as each dispatch asks for
     pub amount_of_samples: u32,
     pub max_depth: u32,
+
+    /// Bounces before Russian roulette may end a path, `max_depth` or more turns it off
+    pub russian_roulette_depth: u32,
+
+    pub background: Background,
+    pub environment: EnvironmentSettings,
+
+    /// Amount of spheres and triangles, their buffers hold a placeholder when there are none
+    pub amount_of_spheres: u32,
+    pub amount_of_triangles: u32,
+
+    /// Amount of emissive spheres in the light buffer
+    pub amount_of_lights: u32,
+
+    pub sampler: SamplerKind,
+
+    /// Nonzero to add to the [`AovAccumulation`] buffer, which the shader doesn't touch otherwise
+    pub write_aovs: u32,
+}
+
+/// Threads per workgroup along x and y, has to match the `compute` attribute on [`main`]
+pub const WORKGROUP_SIZE: Vec2<u32> = Vec2::new(8, 8);
+
+/// What a single dispatch renders, a range of samples over one tile of the image. Bound as a
+/// uniform, where struct members like the vectors are aligned to 16 bytes, so they're stored as
+/// scalars
+#[derive(Clone, Copy, Default, Zeroable, Pod)]
+#[repr(C)]
+pub struct DispatchSettings {
+    tile_offset_x: u32,
+    tile_offset_y: u32,
+    tile_width: u32,
+    tile_height: u32,
+
+    /// Index of the first sample's seed, pixels that are further along carry on from their own
+    pub sample_offset: u32,
+
+    /// Amount of samples taken by every invocation
+    pub amount_of_samples: u32,
+}
+
+impl DispatchSettings {
+    pub fn new(
+        tile_offset: Vec2<u32>,
+        tile_size: Vec2<u32>,
+        sample_offset: u32,
+        amount_of_samples: u32,
+    ) -> Self {
+        Self {
+            tile_offset_x: tile_offset.x,
+            tile_offset_y: tile_offset.y,
+            tile_width: tile_size.x,
+            tile_height: tile_size.y,
+            sample_offset,
+            amount_of_samples,
+        }
+    }
+
+    /// Pixel position of the tile's upper left corner
+    pub fn tile_offset(self) -> Vec2<u32> {
+        Vec2::new(self.tile_offset_x, self.tile_offset_y)
+    }
+
+    /// Size of the tile, clipped to the screen, the output buffer holds one tile row by row
+    pub fn tile_size(self) -> Vec2<u32> {
+        Vec2::new(self.tile_width, self.tile_height)
+    }
+}
+
+/// Running total of a pixel, stopping or continuing a render at any point keeps it valid
+#[derive(Clone, Copy, Default, Zeroable, Pod)]
+#[repr(C)]
+pub struct Accumulation {
+    /// Sum of the linear colors of all samples
+    pub sum: Vec3<f32>,
+
+    pub samples: u32,
+
+    /// Sum of the squared luminances of all samples, for the variance
+    pub luminance_squared: f32,
+
+    /// Set by the runner once the pixel has enough samples, the shader leaves it alone after
+    pub converged: u32,
+
+    /// Sum of the rays traced along the paths of all samples, not counting shadow rays
+    pub path_length: u32,
+}
+
+/// Running totals of what the camera rays of a pixel hit first, kept next to its
+/// [`Accumulation`] when [`RaytraceSettings::write_aovs`] is set
+#[derive(Clone, Copy, Default, Zeroable, Pod)]
+#[repr(C)]
+pub struct AovAccumulation {
+    /// Sum of the albedos at the first hits, guides the denoiser
+    pub albedo: Vec3<f32>,
+
+    /// Sum of the normals at the first hits, guides the denoiser
+    pub normal: Vec3<f32>,
+
+    /// Sum of the first hit points
+    pub position: Vec3<f32>,
+
+    /// Sum of the distances from the camera to the first hits
+    pub depth: f32,
+
+    /// Samples added so far, fewer than the accumulation's when the passes were turned on later
+    pub samples: u32,
+
+    /// Samples that hit something, the rest saw the background
+    pub hits: u32,
+
+    /// Object the first sample hit, [`NO_OBJECT`] when it missed
+    pub object_id: u32,
+
+    /// Material the first sample hit, [`NO_OBJECT`] when it missed
+    pub material_id: u32,
+}
+
+/// Object and material id of pixels whose first sample hit the background
+pub const NO_OBJECT: u32 = u32::MAX;
+
+/// Rec. 709 luminance weights, the primaries the renderer works in
+pub fn luminance(color: Vec3<f32>) -> f32 {
+    color.dot(Vec3::new(0.2126, 0.7152, 0.0722))
+}
+
+impl Accumulation {
+    /// Average color of the samples so far, black before the first
+    pub fn average(self) -> Vec3<f32> {
+        if self.samples == 0 {
+            Vec3::zero()
+        } else {
+            self.sum / self.samples as f32
+        }
+    }
+
+    /// Sample variance of the luminance, zero before the second sample
+    pub fn luminance_variance(self) -> f32 {
+        if self.samples < 2 {
+            return 0.;
+        }
+
+        let samples = self.samples as f32;
+        let mean = luminance(self.sum) / samples;
+        let variance = (self.luminance_squared / samples - mean * mean) * samples / (samples - 1.);
+
+        // Rounding can push it slightly below zero for pixels that barely vary
+        Float::max(variance, 0.)
+    }
+}
+
+impl AovAccumulation {
+    fn mean(self, sum: Vec3<f32>) -> Vec3<f32> {
+        if self.samples == 0 {
+            Vec3::zero()
+        } else {
+            sum / self.samples as f32
+        }
+    }
+
+    /// Average albedo of the first hits, black where the camera rays missed
+    pub fn average_albedo(self) -> Vec3<f32> {
+        self.mean(self.albedo)
+    }
+
+    /// Average normal of the first hits, shorter than one along edges and zero where the camera
+    /// rays missed
+    pub fn average_normal(self) -> Vec3<f32> {
+        self.mean(self.normal)
+    }
+
+    /// Average first hit point of the samples that hit something, zero if none did
+    pub fn average_position(self) -> Vec3<f32> {
+        if self.hits == 0 {
+            Vec3::zero()
+        } else {
+            self.position / self.hits as f32
+        }
+    }
+
+    /// Average distance to the first hits of the samples that hit something, zero if none did
+    pub fn average_depth(self) -> f32 {
+        if self.hits == 0 {
+            0.
+        } else {
+            self.depth / self.hits as f32
+        }
+    }
+}
+
+/// Result of tracing one camera ray, the first hit is only filled in when the passes are written
+#[derive(Clone, Copy, Default)]
+struct PathSample {
+    color: Vec3<f32>,
+
+    /// Albedo of the first surface, white for glass which lets everything through
+    albedo: Vec3<f32>,
+
+    /// Normal of the first surface, facing the camera
+    normal: Vec3<f32>,
+
+    /// Whether the camera ray hit anything, the rest of the first hit is zero if not
+    hit: bool,
+    position: Vec3<f32>,
+    depth: f32,
+    object_id: u32,
+    material_id: u32,
+
+    /// Rays traced along the path
+    length: u32,
 }
 
 #[derive(Clone, Copy, Zeroable, Pod)]
@@ -42,107 +275,335 @@ pub struct Viewport {
     pub vertical_defocus_disk: Vec3<f32>,
 }
 
-fn raytrace_spheres(spheres: &[Sphere], ray: Ray, range: Range) -> RayHit {
-    let mut closest_hit = RayHit::none();
-    let mut closest_distance = range.max;
+#[allow(clippy::too_many_arguments)]
+fn ray_color(
+    ray: Ray,
+    world: World,
+    lights: Lights,
+    textures: Textures,
+    environment: Environment,
+    max_depth: u32,
+    russian_roulette_depth: u32,
+    background: Background,
+    write_aovs: bool,
+    sampler: &mut Sampler,
+) -> PathSample {
+    // Radiance collected so far with the first hit, and how much of the next bounce's light
+    // reaches the camera
+    let mut sample = PathSample {
+        object_id: NO_OBJECT,
+        material_id: NO_OBJECT,
+        ..Default::default()
+    };
+    let mut accumulated_color = Vec3::one();
+    let mut next_ray = ray;
 
-    for i in 0..spheres.len() {
-        let ray_hit = spheres[i].raycast(
-            ray,
-            Range {
-                min: range.min,
-                max: closest_distance,
-            },
-        );
+    // Density the last bounce was sampled with, zero for camera rays and specular bounces which
+    // light sampling can't produce
+    let mut previous_pdf = 0.;
+
+    for depth in 0..max_depth {
+        sampler.start_bounce(depth);
+        let ray_hit = world.raycast(next_ray, Range::new(0.001, Float::max_value()));
+        sample.length += 1;
 
         if ray_hit.did_hit {
-            closest_distance = ray_hit.distance;
-            closest_hit = ray_hit;
-        }
-    }
+            if depth == 0 && write_aovs {
+                sample.albedo = match ray_hit.material.reflection {
+                    Reflection::Glass => Vec3::one(),
+                    _ => ray_hit.material.albedo_at(ray_hit, textures),
+                };
+                sample.normal = ray_hit.normal;
+                sample.hit = true;
+                sample.position = ray_hit.point;
+                sample.depth = (ray_hit.point - ray.origin).magnitude();
+                sample.object_id = ray_hit.scene_id;
+                sample.material_id = ray_hit.material.id;
+            }
 
-    closest_hit
-}
+            // Lights hit by a bounce could also have been sampled directly from its origin
+            let weight = if previous_pdf > 0. {
+                let light_pdf = lights.pdf(ray_hit.object_id, next_ray.origin);
 
-fn ray_color(ray: Ray, spheres: &[Sphere], max_depth: u32, rand: &mut Rand) -> Vec3<f32> {
-    let mut accumulated_color = Vec3::one();
-    let mut next_ray = ray;
+                power_heuristic(previous_pdf, light_pdf)
+            } else {
+                1.
+            };
 
-    for _ in 0..max_depth {
-        let ray_hit = raytrace_spheres(spheres, next_ray, Range::new(0.001, Float::max_value()));
+            sample.color += accumulated_color * ray_hit.material.emitted() * weight;
 
-        if ray_hit.did_hit {
-            let scatter_result = ray_hit.material.scatter(next_ray, ray_hit, rand);
+            let scatter_result = ray_hit
+                .material
+                .scatter(next_ray, ray_hit, textures, sampler);
 
-            if scatter_result.did_scatter {
-                accumulated_color *= scatter_result.attenuation;
-                next_ray = scatter_result.scattered;
-            } else {
+            if !scatter_result.did_scatter {
                 // Didn't scatter
-                return Vec3::zero();
+                return sample;
+            }
+
+            if scatter_result.pdf > 0. {
+                sample.color +=
+                    accumulated_color * sample_light(world, lights, textures, ray_hit, sampler);
+            }
+
+            accumulated_color *= scatter_result.attenuation;
+            next_ray = scatter_result.scattered;
+            previous_pdf = scatter_result.pdf;
+
+            // Russian roulette, paths that carry little light are likely to end here and the
+            // ones that go on make up for them, keeping the average the same. Only decided when
+            // there's another bounce to come
+            if depth + 1 >= russian_roulette_depth && depth + 1 < max_depth {
+                let survival = Float::min(
+                    Float::max(
+                        Float::max(accumulated_color.x, accumulated_color.y),
+                        accumulated_color.z,
+                    ),
+                    1.,
+                );
+
+                if sampler.gen_float() >= survival {
+                    return sample;
+                }
+
+                accumulated_color /= survival;
             }
         } else {
-            // Didn't hit anything
-            let unit_direction = next_ray.direction.normalized();
-            let a = (unit_direction.y + 1.) / 2.;
-            let background_color = Vec3::broadcast(1. - a) + a * Vec3::new(0.5, 0.7, 1.);
+            // Didn't hit anything, the environment map could also have been sampled directly
+            let weight = if previous_pdf > 0. {
+                let light_pdf = lights.environment_pdf(next_ray.direction);
 
-            accumulated_color *= background_color;
+                power_heuristic(previous_pdf, light_pdf)
+            } else {
+                1.
+            };
 
-            return accumulated_color;
+            sample.color +=
+                accumulated_color * background.color(next_ray.direction, environment) * weight;
+
+            return sample;
         }
     }
 
     // Reached max depth
-    Vec3::zero()
+    sample
 }
 
-fn pixel_sample_offset(rand: &mut Rand) -> Vec2<f32> {
-    rand.gen_vec2() - (Vec2::one() / 2.) // From -0.5 to 0.5
+/// Next event estimation, light reaching the hit point directly from a sampled light
+fn sample_light(
+    world: World,
+    lights: Lights,
+    textures: Textures,
+    ray_hit: RayHit,
+    sampler: &mut Sampler,
+) -> Vec3<f32> {
+    let light_sample = lights.sample(ray_hit.point, sampler);
+    if !light_sample.did_sample {
+        return Vec3::zero();
+    }
+
+    let bsdf = ray_hit
+        .material
+        .evaluate(ray_hit, light_sample.direction, textures);
+    if bsdf == Vec3::zero() {
+        return Vec3::zero();
+    }
+
+    let shadow_ray = Ray {
+        origin: ray_hit.point,
+        direction: light_sample.direction,
+    };
+    let shadow_hit = world.raycast(shadow_ray, Range::new(0.001, Float::max_value()));
+
+    // Something else is in the way
+    let is_visible = if shadow_hit.did_hit {
+        shadow_hit.object_id == light_sample.object_id
+    } else {
+        light_sample.object_id == ENVIRONMENT_ID
+    };
+
+    if !is_visible {
+        return Vec3::zero();
+    }
+
+    let bsdf_pdf = ray_hit.material.pdf(ray_hit, light_sample.direction);
+    let weight = power_heuristic(light_sample.pdf, bsdf_pdf);
+
+    bsdf * light_sample.emitted * weight / light_sample.pdf
 }
 
-fn defocus_sample_offset(rand: &mut Rand) -> Vec2<f32> {
-    rand.gen_in_unit_disk()
+fn pixel_sample_offset(sampler: &mut Sampler) -> Vec2<f32> {
+    sampler.gen_vec2() - (Vec2::one() / 2.) // From -0.5 to 0.5
 }
 
-#[spirv(compute(threads(1)))]
+fn defocus_sample_offset(sampler: &mut Sampler) -> Vec2<f32> {
+    sampler.gen_in_unit_disk()
+}
+
+/// Ray through a random point of the pixel, starting at a random point of the defocus disk
+fn camera_ray(viewport: Viewport, pixel_position: Vec2<u32>, sampler: &mut Sampler) -> Ray {
+    let sample_position = pixel_position.as_::<f32>() + pixel_sample_offset(sampler);
+
+    let pixel_center = viewport.upper_left_pixel_position
+        + sample_position.x * viewport.horizontal_pixel_delta
+        + sample_position.y * viewport.vertical_pixel_delta;
+
+    let defocus_offset = defocus_sample_offset(sampler);
+    let ray_origin = viewport.origin
+        + defocus_offset.x * viewport.horizontal_defocus_disk
+        + defocus_offset.y * viewport.vertical_defocus_disk;
+
+    Ray {
+        origin: ray_origin,
+        direction: pixel_center - ray_origin,
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+#[spirv(compute(threads(8, 8)))]
 pub fn main(
-    #[spirv(global_invocation_id)] pixel_position: glam::UVec3,
-    #[spirv(uniform, descriptor_set = 0, binding = 0)] &seed: &u32,
+    #[spirv(global_invocation_id)] tile_position: glam::UVec3,
+    #[spirv(uniform, descriptor_set = 0, binding = 0)] &dispatch: &DispatchSettings,
     #[spirv(storage_buffer, descriptor_set = 0, binding = 1)] &raytrace_settings: &RaytraceSettings,
     #[spirv(storage_buffer, descriptor_set = 0, binding = 2)] spheres: &[Sphere],
-    #[spirv(storage_buffer, descriptor_set = 0, binding = 3)] output: &mut [Vec3<f32>],
+    #[spirv(storage_buffer, descriptor_set = 0, binding = 3)] sphere_bvh_nodes: &[BvhNode],
+    #[spirv(storage_buffer, descriptor_set = 0, binding = 4)] vertices: &[Vertex],
+    #[spirv(storage_buffer, descriptor_set = 0, binding = 5)] triangles: &[Triangle],
+    #[spirv(storage_buffer, descriptor_set = 0, binding = 6)] triangle_bvh_nodes: &[BvhNode],
+    #[spirv(storage_buffer, descriptor_set = 0, binding = 7)] lights: &[u32],
+    #[spirv(storage_buffer, descriptor_set = 0, binding = 8)] textures: &[Texture],
+    #[spirv(storage_buffer, descriptor_set = 0, binding = 9)] texels: &[Vec3<f32>],
+    #[spirv(storage_buffer, descriptor_set = 0, binding = 10)] environment_distribution: &[f32],
+    #[spirv(storage_buffer, descriptor_set = 0, binding = 11)] blue_noise: &[f32],
+    #[spirv(storage_buffer, descriptor_set = 0, binding = 12)] seeds: &[u32],
+    #[spirv(storage_buffer, descriptor_set = 0, binding = 13)] output: &mut [Accumulation],
+    #[spirv(storage_buffer, descriptor_set = 0, binding = 14)] aov_output: &mut [AovAccumulation],
 ) {
-    let pixel_position = Vec2::new(pixel_position.x, pixel_position.y);
+    let tile_offset = dispatch.tile_offset();
+    let tile_size = dispatch.tile_size();
+    let DispatchSettings {
+        sample_offset,
+        amount_of_samples,
+        ..
+    } = dispatch;
+
+    let tile_position = Vec2::new(tile_position.x, tile_position.y);
+    let pixel_position = tile_offset + tile_position;
 
     let RaytraceSettings {
         viewport,
         screen_size,
-        amount_of_samples,
         max_depth,
+        russian_roulette_depth,
+        background,
+        environment,
+        amount_of_spheres,
+        amount_of_triangles,
+        amount_of_lights,
+        sampler: sampler_kind,
+        write_aovs,
+        ..
     } = raytrace_settings;
+    let write_aovs = write_aovs != 0;
 
-    let mut rand = Rand::from(pixel_position.with_z(seed));
-    let sample_position = pixel_position.as_::<f32>() + pixel_sample_offset(&mut rand);
+    // Workgroups at the edges stick out of the tile
+    if tile_position.x >= tile_size.x
+        || tile_position.y >= tile_size.y
+        || pixel_position.x >= screen_size.x
+        || pixel_position.y >= screen_size.y
+    {
+        return;
+    }
 
-    let pixel_center = viewport.upper_left_pixel_position
-        + sample_position.x * viewport.horizontal_pixel_delta
-        + sample_position.y * viewport.vertical_pixel_delta;
+    let world = World {
+        spheres: Bvh::new(sphere_bvh_nodes, spheres, amount_of_spheres),
+        mesh: Bvh::new(
+            triangle_bvh_nodes,
+            Mesh::new(vertices, triangles),
+            amount_of_triangles,
+        ),
+    };
+    let textures = Textures::new(textures, texels);
+    let environment = Environment::new(environment, textures, environment_distribution);
+    let has_environment = matches!(background, Background::Environment);
+    let lights = Lights::new(
+        spheres,
+        lights,
+        amount_of_lights,
+        environment,
+        has_environment,
+    );
 
-    let defocus_offset = defocus_sample_offset(&mut rand);
-    let ray_origin = viewport.origin
-        + defocus_offset.x * viewport.horizontal_defocus_disk
-        + defocus_offset.y * viewport.vertical_defocus_disk;
+    let index = (tile_position.y * tile_size.x + tile_position.x) as usize;
 
-    let ray_direction = pixel_center - ray_origin;
+    // Adding every sample on its own keeps the result the same however the samples are split
+    // over dispatches
+    let mut accumulation = output[index];
+    if accumulation.converged != 0 {
+        return;
+    }
 
-    let ray = Ray {
-        origin: ray_origin,
-        direction: ray_direction,
+    // Only a placeholder is bound when the passes are off
+    let mut aovs = if write_aovs {
+        aov_output[index]
+    } else {
+        AovAccumulation::default()
     };
 
-    let color = ray_color(ray, spheres, max_depth, &mut rand);
+    // A pass stopped between tiles leaves some pixels ahead, they only take the samples they lack
+    let first_sample = if accumulation.samples > sample_offset {
+        accumulation.samples
+    } else {
+        sample_offset
+    };
+    for sample in first_sample..(sample_offset + amount_of_samples) {
+        let mut sampler = Sampler::new(
+            sampler_kind,
+            pixel_position,
+            blue_noise,
+            sample,
+            seeds[0],
+            seeds[sample as usize],
+        );
+        let ray = camera_ray(viewport, pixel_position, &mut sampler);
+
+        let path = ray_color(
+            ray,
+            world,
+            lights,
+            textures,
+            environment,
+            max_depth,
+            russian_roulette_depth,
+            background,
+            write_aovs,
+            &mut sampler,
+        );
+
+        accumulation.sum += path.color;
+        accumulation.luminance_squared += luminance(path.color) * luminance(path.color);
+        accumulation.path_length += path.length;
+        accumulation.samples += 1;
 
-    output[(pixel_position.y * screen_size.x + pixel_position.x) as usize] +=
-        color / (amount_of_samples as f32);
+        if write_aovs {
+            if aovs.samples == 0 {
+                aovs.object_id = path.object_id;
+                aovs.material_id = path.material_id;
+            }
+
+            aovs.albedo += path.albedo;
+            aovs.normal += path.normal;
+            if path.hit {
+                aovs.position += path.position;
+                aovs.depth += path.depth;
+                aovs.hits += 1;
+            }
+            aovs.samples += 1;
+        }
+    }
+
+    output[index] = accumulation;
+    if write_aovs {
+        aov_output[index] = aovs;
+    }
 }
@@ -0,0 +1,199 @@
+use core::f32::consts::PI;
+
+use spirv_std::num_traits::Float;
+use vek::Vec3;
+
+use crate::{environment::Environment, sampler::Sampler, sphere::Sphere};
+
+/// Object id of environment light samples, shadow rays towards them must not hit anything
+pub const ENVIRONMENT_ID: u32 = u32::MAX;
+
+#[derive(Clone, Copy, Default)]
+pub struct LightSample {
+    /// Whether or not a light could be sampled, false when the point is inside the light
+    pub did_sample: bool,
+
+    /// Direction towards the light, unit length
+    pub direction: Vec3<f32>,
+
+    /// Probability density of the direction, over solid angle
+    pub pdf: f32,
+
+    /// Object id of the sampled sphere, a shadow ray has to hit it for the light to be visible, or
+    /// [`ENVIRONMENT_ID`]
+    pub object_id: u32,
+
+    /// Radiance emitted by the sampled light
+    pub emitted: Vec3<f32>,
+}
+
+impl LightSample {
+    pub fn none() -> Self {
+        Default::default()
+    }
+}
+
+/// Emissive spheres and the environment map, which can be sampled directly
+#[derive(Clone, Copy)]
+pub struct Lights<'a> {
+    spheres: &'a [Sphere],
+
+    /// Indices into the spheres, the buffer is padded when there are no lights so `amount` is
+    /// used instead of its length
+    indices: &'a [u32],
+    amount: u32,
+
+    environment: Environment<'a>,
+
+    /// Whether the environment map is the background
+    has_environment: bool,
+}
+
+/// One minus the cosine of the half angle of the cone the sphere covers as seen from `point`,
+/// `None` when the point is inside the sphere
+fn cone_size(sphere: Sphere, point: Vec3<f32>) -> Option<f32> {
+    let distance_squared = (sphere.center - point).magnitude_squared();
+    let radius_squared = sphere.radius * sphere.radius;
+
+    if distance_squared <= radius_squared {
+        return None;
+    }
+
+    // Rewritten to stay precise for small or distant spheres
+    let sine_squared = radius_squared / distance_squared;
+
+    Some(sine_squared / (1. + Float::sqrt(1. - sine_squared)))
+}
+
+/// Orthonormal basis around `w`, returns the two tangents
+fn tangents(w: Vec3<f32>) -> (Vec3<f32>, Vec3<f32>) {
+    let helper = if Float::abs(w.x) > 0.9 {
+        Vec3::unit_y()
+    } else {
+        Vec3::unit_x()
+    };
+
+    let v = Vec3::cross(w, helper).normalized();
+    let u = Vec3::cross(w, v);
+
+    (u, v)
+}
+
+impl<'a> Lights<'a> {
+    pub fn new(
+        spheres: &'a [Sphere],
+        indices: &'a [u32],
+        amount: u32,
+        environment: Environment<'a>,
+        has_environment: bool,
+    ) -> Self {
+        Self {
+            spheres,
+            indices,
+            amount,
+            environment,
+            has_environment,
+        }
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.amount == 0 && !self.has_environment
+    }
+
+    /// Chance of sampling the environment instead of a sphere, split evenly when there are both
+    fn environment_probability(self) -> f32 {
+        if !self.has_environment {
+            0.
+        } else if self.amount == 0 {
+            1.
+        } else {
+            0.5
+        }
+    }
+
+    /// Picks either the environment map, proportional to its brightness, or a sphere uniformly
+    /// and a direction inside the cone it covers
+    pub fn sample(self, point: Vec3<f32>, sampler: &mut Sampler) -> LightSample {
+        if self.is_empty() {
+            return LightSample::none();
+        }
+
+        let environment_probability = self.environment_probability();
+
+        if sampler.gen_float() < environment_probability {
+            let sample = self.environment.sample(sampler);
+
+            return LightSample {
+                did_sample: sample.pdf > 0.,
+                direction: sample.direction,
+                pdf: sample.pdf * environment_probability,
+                object_id: ENVIRONMENT_ID,
+                emitted: self.environment.radiance(sample.direction),
+            };
+        }
+
+        let object_id = self.indices[sampler.gen_index(self.amount) as usize];
+        let sphere = self.spheres[object_id as usize];
+
+        let Some(cone_size) = cone_size(sphere, point) else {
+            return LightSample::none();
+        };
+
+        let value = sampler.gen_vec2();
+        let angle = 2. * PI * value.x;
+        let cosine = 1. - value.y * cone_size;
+        let sine = Float::sqrt(Float::max(1. - cosine * cosine, 0.));
+
+        let w = (sphere.center - point).normalized();
+        let (u, v) = tangents(w);
+        let direction =
+            u * (Float::cos(angle) * sine) + v * (Float::sin(angle) * sine) + w * cosine;
+
+        LightSample {
+            did_sample: true,
+            direction,
+            pdf: self.pdf(object_id, point),
+            object_id,
+            emitted: sphere.material.emitted(),
+        }
+    }
+
+    /// Probability density of [`Lights::sample`] choosing the direction from `point` towards the
+    /// sphere with `object_id`, zero if it isn't a light
+    pub fn pdf(self, object_id: u32, point: Vec3<f32>) -> f32 {
+        if self.amount == 0 || object_id as usize >= self.spheres.len() {
+            return 0.;
+        }
+
+        let sphere = self.spheres[object_id as usize];
+        if sphere.material.emitted() == Vec3::zero() {
+            return 0.;
+        }
+
+        match cone_size(sphere, point) {
+            Some(cone_size) => {
+                let sphere_probability = (1. - self.environment_probability()) / self.amount as f32;
+
+                sphere_probability / (2. * PI * cone_size)
+            }
+            None => 0.,
+        }
+    }
+
+    /// Probability density of [`Lights::sample`] choosing `direction` on the environment map
+    pub fn environment_pdf(self, direction: Vec3<f32>) -> f32 {
+        if !self.has_environment {
+            return 0.;
+        }
+
+        self.environment.pdf(direction) * self.environment_probability()
+    }
+}
+
+/// Weight for a sample from the strategy with `pdf`, when `other_pdf` could also have produced it
+pub fn power_heuristic(pdf: f32, other_pdf: f32) -> f32 {
+    let pdf = pdf * pdf;
+    let other_pdf = other_pdf * other_pdf;
+
+    pdf / (pdf + other_pdf)
+}
@@ -1,11 +1,14 @@
+use core::f32::consts::PI;
+
 use bytemuck::{Pod, Zeroable};
 use spirv_std::num_traits::Float;
 use vek::Vec3;
 
 use crate::{
     data::{Face, RayHit, ScatterResult},
-    rand::Rand,
     ray::Ray,
+    sampler::Sampler,
+    texture::Textures,
 };
 
 #[derive(Clone, Copy, Default)]
@@ -15,6 +18,7 @@ pub enum Reflection {
     Diffuse,
     Metal,
     Glass,
+    Emissive,
 }
 
 unsafe impl Zeroable for Reflection {}
@@ -27,6 +31,15 @@ pub struct Material {
     pub albedo: Vec3<f32>,
     pub fuzz: f32,
     pub refraction_index: f32,
+
+    /// Multiplier for the albedo of emissive materials
+    pub intensity: f32,
+
+    /// Index into the textures the albedo gets multiplied with, the first texture is plain white
+    pub texture: u32,
+
+    /// Shared by identical materials in the scene, for the material id pass
+    pub id: u32,
 }
 
 impl Material {
@@ -55,11 +68,75 @@ impl Material {
         }
     }
 
-    pub fn scatter(self, ray: Ray, ray_hit: RayHit, rand: &mut Rand) -> ScatterResult {
+    /// Emits light of the given color, doesn't scatter
+    pub fn emissive(color: Vec3<f32>, intensity: f32) -> Self {
+        Self {
+            reflection: Reflection::Emissive,
+            albedo: color,
+            intensity,
+            ..Default::default()
+        }
+    }
+
+    /// Multiplies the albedo of diffuse and metal materials with a texture
+    pub fn with_texture(self, texture: u32) -> Self {
+        Self { texture, ..self }
+    }
+
+    /// Albedo at the hit point, with the texture applied
+    pub fn albedo_at(self, ray_hit: RayHit, textures: Textures) -> Vec3<f32> {
+        self.albedo * textures.sample(self.texture, ray_hit.uv, ray_hit.point)
+    }
+
+    /// Radiance emitted from the surface
+    pub fn emitted(self) -> Vec3<f32> {
         match self.reflection {
-            Reflection::Diffuse => scatter_diffuse(self.albedo, ray_hit, rand),
-            Reflection::Metal => scatter_metal(self.albedo, self.fuzz, ray, ray_hit, rand),
-            Reflection::Glass => scatter_glass(self.refraction_index, ray, ray_hit, rand),
+            Reflection::Emissive => self.albedo * self.intensity,
+            _ => Vec3::zero(),
+        }
+    }
+
+    /// The bsdf times the cosine term for light arriving from `direction`, zero for specular
+    /// materials since no sampled direction can hit their reflection exactly
+    pub fn evaluate(self, ray_hit: RayHit, direction: Vec3<f32>, textures: Textures) -> Vec3<f32> {
+        match self.reflection {
+            Reflection::Diffuse => {
+                let cosine = Vec3::dot(ray_hit.normal, direction.normalized());
+
+                self.albedo_at(ray_hit, textures) * Float::max(cosine, 0.) / PI
+            }
+            _ => Vec3::zero(),
+        }
+    }
+
+    /// Probability density of [`Material::scatter`] producing `direction`
+    pub fn pdf(self, ray_hit: RayHit, direction: Vec3<f32>) -> f32 {
+        match self.reflection {
+            Reflection::Diffuse => diffuse_pdf(ray_hit.normal, direction),
+            _ => 0.,
+        }
+    }
+
+    pub fn scatter(
+        self,
+        ray: Ray,
+        ray_hit: RayHit,
+        textures: Textures,
+        sampler: &mut Sampler,
+    ) -> ScatterResult {
+        match self.reflection {
+            Reflection::Diffuse => {
+                scatter_diffuse(self.albedo_at(ray_hit, textures), ray_hit, sampler)
+            }
+            Reflection::Metal => scatter_metal(
+                self.albedo_at(ray_hit, textures),
+                self.fuzz,
+                ray,
+                ray_hit,
+                sampler,
+            ),
+            Reflection::Glass => scatter_glass(self.refraction_index, ray, ray_hit, sampler),
+            Reflection::Emissive => ScatterResult::none(),
         }
     }
 }
@@ -77,8 +154,15 @@ fn reflectance(cosine: f32, refraction_ratio: f32) -> f32 {
     r0 + (1. - r0) * Float::powi(1. - cosine, 5)
 }
 
-fn scatter_diffuse(albedo: Vec3<f32>, ray_hit: RayHit, rand: &mut Rand) -> ScatterResult {
-    let mut scatter_direction = ray_hit.normal + rand.gen_unit_vector();
+/// Diffuse scattering is cosine weighted
+fn diffuse_pdf(normal: Vec3<f32>, direction: Vec3<f32>) -> f32 {
+    let cosine = Vec3::dot(normal, direction.normalized());
+
+    Float::max(cosine, 0.) / PI
+}
+
+fn scatter_diffuse(albedo: Vec3<f32>, ray_hit: RayHit, sampler: &mut Sampler) -> ScatterResult {
+    let mut scatter_direction = ray_hit.normal + sampler.gen_unit_vector();
 
     // Catch degenerate scatter direction
     if is_near_zero(scatter_direction) {
@@ -90,11 +174,13 @@ fn scatter_diffuse(albedo: Vec3<f32>, ray_hit: RayHit, rand: &mut Rand) -> Scatt
         direction: scatter_direction,
     };
     let attenuation = albedo;
+    let pdf = diffuse_pdf(ray_hit.normal, scatter_direction);
 
     ScatterResult {
         did_scatter: true,
         scattered,
         attenuation,
+        pdf,
     }
 }
 
@@ -103,13 +189,13 @@ pub fn scatter_metal(
     fuzz: f32,
     ray: Ray,
     ray_hit: RayHit,
-    rand: &mut Rand,
+    sampler: &mut Sampler,
 ) -> ScatterResult {
     let reflected = ray.direction.normalized().reflected(ray_hit.normal);
 
     let scattered = Ray {
         origin: ray_hit.point,
-        direction: reflected + rand.gen_unit_vector() * fuzz,
+        direction: reflected + sampler.gen_unit_vector() * fuzz,
     };
     let attenuation = albedo;
 
@@ -118,6 +204,7 @@ pub fn scatter_metal(
             did_scatter: true,
             scattered,
             attenuation,
+            pdf: 0.,
         }
     } else {
         ScatterResult::none()
@@ -128,7 +215,7 @@ pub fn scatter_glass(
     refraction_index: f32,
     ray: Ray,
     ray_hit: RayHit,
-    rand: &mut Rand,
+    sampler: &mut Sampler,
 ) -> ScatterResult {
     let refraction_ratio = match ray_hit.face {
         Face::Front => 1. / refraction_index,
@@ -140,7 +227,7 @@ pub fn scatter_glass(
     let sin_theta = Float::sqrt(1. - cos_theta * cos_theta);
 
     let cannot_refract = (refraction_ratio * sin_theta > 1.)
-        || (reflectance(cos_theta, refraction_ratio) > rand.gen_float());
+        || (reflectance(cos_theta, refraction_ratio) > sampler.gen_float());
 
     let direction = if cannot_refract {
         unit_direction.reflected(ray_hit.normal)
@@ -158,5 +245,6 @@ pub fn scatter_glass(
         did_scatter: true,
         scattered,
         attenuation,
+        pdf: 0.,
     }
 }
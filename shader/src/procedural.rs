@@ -0,0 +1,138 @@
+use spirv_std::num_traits::Float;
+use vek::Vec3;
+
+use crate::rand::{hash1, hash3, uint_to_u01_float};
+
+/// Integer lattice point containing `point`, wrapped to unsigned for hashing
+fn lattice(point: Vec3<f32>) -> Vec3<u32> {
+    Vec3::new(
+        Float::floor(point.x) as i32 as u32,
+        Float::floor(point.y) as i32 as u32,
+        Float::floor(point.z) as i32 as u32,
+    )
+}
+
+/// Three uniform values from zero to one, derived from a lattice point
+fn hash_vec3(cell: Vec3<u32>) -> Vec3<f32> {
+    let x = hash3(cell);
+    let y = hash1(x);
+    let z = hash1(y);
+
+    Vec3::new(
+        uint_to_u01_float(x),
+        uint_to_u01_float(y),
+        uint_to_u01_float(z),
+    )
+}
+
+/// Quintic smoothstep, its first and second derivatives vanish at the lattice points
+fn fade(t: f32) -> f32 {
+    t * t * t * (t * (t * 6. - 15.) + 10.)
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+/// Checkerboard of unit cubes, true for the cubes where the sum of the coordinates is even
+pub fn checker(point: Vec3<f32>) -> bool {
+    let cell = Float::floor(point.x) + Float::floor(point.y) + Float::floor(point.z);
+
+    cell - 2. * Float::floor(cell / 2.) < 0.5
+}
+
+/// Lattice point offset by a small amount in each direction, wrapping around
+fn offset_cell(cell: Vec3<u32>, offset: Vec3<u32>) -> Vec3<u32> {
+    Vec3::new(
+        cell.x.wrapping_add(offset.x),
+        cell.y.wrapping_add(offset.y),
+        cell.z.wrapping_add(offset.z),
+    )
+}
+
+/// Dot product of a corner's random gradient with the offset from that corner to the point
+fn corner_dot(cell: Vec3<u32>, local: Vec3<f32>, corner: Vec3<u32>) -> f32 {
+    let gradient = hash_vec3(offset_cell(cell, corner)) * 2. - 1.;
+
+    Vec3::dot(gradient, local - corner.as_::<f32>())
+}
+
+/// Perlin gradient noise, roughly from minus one to one
+pub fn perlin(point: Vec3<f32>) -> f32 {
+    let cell = lattice(point);
+    let local = point - point.map(Float::floor);
+
+    let u = fade(local.x);
+    let v = fade(local.y);
+    let w = fade(local.z);
+
+    let x00 = lerp(
+        corner_dot(cell, local, Vec3::new(0, 0, 0)),
+        corner_dot(cell, local, Vec3::new(1, 0, 0)),
+        u,
+    );
+    let x10 = lerp(
+        corner_dot(cell, local, Vec3::new(0, 1, 0)),
+        corner_dot(cell, local, Vec3::new(1, 1, 0)),
+        u,
+    );
+    let x01 = lerp(
+        corner_dot(cell, local, Vec3::new(0, 0, 1)),
+        corner_dot(cell, local, Vec3::new(1, 0, 1)),
+        u,
+    );
+    let x11 = lerp(
+        corner_dot(cell, local, Vec3::new(0, 1, 1)),
+        corner_dot(cell, local, Vec3::new(1, 1, 1)),
+        u,
+    );
+
+    lerp(lerp(x00, x10, v), lerp(x01, x11, v), w)
+}
+
+/// Sum of the absolute value of several octaves of noise, each at double the frequency and half
+/// the amplitude
+pub fn turbulence(point: Vec3<f32>, octaves: u32) -> f32 {
+    let mut sum = 0.;
+    let mut scaled_point = point;
+    let mut weight = 1.;
+
+    for _ in 0..octaves {
+        sum += weight * Float::abs(perlin(scaled_point));
+        weight *= 0.5;
+        scaled_point *= 2.;
+    }
+
+    sum
+}
+
+/// Veined pattern from zero to one, a sine wave along z disturbed by turbulence
+pub fn marble(point: Vec3<f32>, octaves: u32) -> f32 {
+    0.5 * (1. + Float::sin(point.z + 10. * turbulence(point, octaves)))
+}
+
+/// Cellular noise, distance from the point to the closest of one random feature point per unit
+/// cube, clamped to one
+pub fn worley(point: Vec3<f32>) -> f32 {
+    let cell = lattice(point);
+    let local = point - point.map(Float::floor);
+
+    let mut closest_squared: f32 = 1.;
+
+    for z in 0..3i32 {
+        for y in 0..3i32 {
+            for x in 0..3i32 {
+                // Neighbouring cells from minus one to one
+                let neighbour = Vec3::new(x - 1, y - 1, z - 1);
+                let neighbour_cell = offset_cell(cell, neighbour.as_::<u32>());
+
+                let feature = hash_vec3(neighbour_cell) + neighbour.as_::<f32>();
+                let distance_squared = (feature - local).magnitude_squared();
+
+                closest_squared = Float::min(closest_squared, distance_squared);
+            }
+        }
+    }
+
+    Float::sqrt(closest_squared)
+}
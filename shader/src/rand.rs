@@ -2,19 +2,24 @@ use vek::{Vec2, Vec3};
 
 use crate::data::Range;
 
+// Overflow is part of the hashing, it has to wrap natively just like it does on the gpu
 pub fn hash1(mut x: u32) -> u32 {
-    x += x << 10;
+    x = x.wrapping_add(x << 10);
     x ^= x >> 6;
-    x += x << 3;
+    x = x.wrapping_add(x << 3);
     x ^= x >> 11;
-    x += x << 15;
+    x = x.wrapping_add(x << 15);
     x
 }
 
 pub fn hash_combine2(x: u32, y: u32) -> u32 {
     const M: u32 = 1664525;
     const C: u32 = 1013904223;
-    let mut seed = (x * M + y + C) * M;
+    let mut seed = x
+        .wrapping_mul(M)
+        .wrapping_add(y)
+        .wrapping_add(C)
+        .wrapping_mul(M);
     // Tempering (from Matsumoto)
     seed ^= seed >> 11;
     seed ^= (seed << 7) & 0x9d2c5680;
@@ -85,16 +90,6 @@ impl Rand {
         self.gen_in_unit_sphere().normalized()
     }
 
-    pub fn gen_on_hemisphere(&mut self, normal: Vec3<f32>) -> Vec3<f32> {
-        let on_unit_sphere = self.gen_unit_vector();
-
-        if on_unit_sphere.dot(normal) > 0. {
-            on_unit_sphere
-        } else {
-            -on_unit_sphere
-        }
-    }
-
     pub fn gen_in_unit_disk(&mut self) -> Vec2<f32> {
         let mut random = || self.gen_range(Range::new(-1., 1.));
 
@@ -0,0 +1,244 @@
+use core::f32::consts::PI;
+
+use bytemuck::{Pod, Zeroable};
+use spirv_std::num_traits::Float;
+use vek::{Vec2, Vec3};
+
+use crate::rand::{hash1, hash2, hash_combine2, Rand};
+
+/// How the random numbers of a sample are chosen
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+#[repr(u32)]
+pub enum SamplerKind {
+    /// Every number independent of the others, from a hash chain
+    #[default]
+    Independent,
+
+    /// Owen-scrambled Sobol points, scrambled differently for every pixel
+    Sobol,
+
+    /// The same Sobol points for every pixel, shifted by a blue noise mask so the error left in
+    /// neighbouring pixels differs, looking like fine grain instead of blotches
+    BlueNoise,
+}
+
+unsafe impl Zeroable for SamplerKind {}
+unsafe impl Pod for SamplerKind {}
+
+/// Side of the blue noise mask, repeated across the image
+pub const BLUE_NOISE_SIZE: u32 = 64;
+
+/// Dimensions set aside for the camera ray, the pixel position and the lens position
+const CAMERA_DIMENSIONS: u32 = 2;
+
+/// Dimensions set aside for every bounce, more than any bounce uses so each starts at the same
+/// dimension in every sample, whatever the previous ones used
+const BOUNCE_DIMENSIONS: u32 = 8;
+
+/// Dimensions with their own place in the blue noise mask
+const BLUE_NOISE_DIMENSIONS: usize = (CAMERA_DIMENSIONS + BOUNCE_DIMENSIONS) as usize;
+
+/// Spelled out in shifts and masks, which every shader target supports
+fn reverse_bits(mut x: u32) -> u32 {
+    x = ((x >> 1) & 0x55555555) | ((x & 0x55555555) << 1);
+    x = ((x >> 2) & 0x33333333) | ((x & 0x33333333) << 2);
+    x = ((x >> 4) & 0x0f0f0f0f) | ((x & 0x0f0f0f0f) << 4);
+    x = ((x >> 8) & 0x00ff00ff) | ((x & 0x00ff00ff) << 8);
+    ((x >> 16) & 0x0000ffff) | ((x & 0x0000ffff) << 16)
+}
+
+/// Hash where every bit only depends on the bits below it, Burley's improvement on Laine and
+/// Karras
+fn laine_karras_permutation(mut x: u32, seed: u32) -> u32 {
+    x = x.wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6c50b47c);
+    x ^= x.wrapping_mul(0xb82f1e52);
+    x ^= x.wrapping_mul(0xc7afe638);
+    x ^= x.wrapping_mul(0x8d22f6e6);
+    x
+}
+
+/// Owen scrambling, every bit flipped depending on the bits above it. "Practical Hash-based Owen
+/// Scrambling", Burley 2020
+fn nested_uniform_scramble(x: u32, seed: u32) -> u32 {
+    reverse_bits(laine_karras_permutation(reverse_bits(x), seed))
+}
+
+/// The first two dimensions of the Sobol sequence, as fixed point fractions
+fn sobol_2d(index: u32) -> Vec2<u32> {
+    let mut y = 0;
+    let mut direction = 1 << 31;
+    let mut bits = index;
+
+    while bits != 0 {
+        if bits & 1 != 0 {
+            y ^= direction;
+        }
+
+        bits >>= 1;
+        direction ^= direction >> 1;
+    }
+
+    Vec2::new(reverse_bits(index), y)
+}
+
+/// Fixed point fraction to a float below one, keeping the highest bits
+fn to_float(x: u32) -> f32 {
+    (x >> 8) as f32 / (1 << 24) as f32
+}
+
+fn fract(x: f32) -> f32 {
+    x - Float::floor(x)
+}
+
+/// Mask value at a pixel, with the tile repeated across the image and moved by an offset picked by
+/// `seed`
+fn blue_noise_at(blue_noise: &[f32], pixel: Vec2<u32>, seed: u32) -> f32 {
+    let offset = Vec2::new(seed, hash1(seed)).map(|x| x % BLUE_NOISE_SIZE);
+    let position = (pixel + offset).map(|x| x % BLUE_NOISE_SIZE);
+
+    blue_noise[(position.y * BLUE_NOISE_SIZE + position.x) as usize]
+}
+
+/// Hands out the random numbers of one sample, dimension by dimension
+pub struct Sampler {
+    kind: SamplerKind,
+    pixel: Vec2<u32>,
+
+    /// Which of the pixel's samples this is
+    index: u32,
+
+    /// Same for every sample of the render
+    seed: u32,
+
+    dimension: u32,
+    rand: Rand,
+
+    /// Blue noise shifts for the camera dimensions and the dimensions of a bounce, each read from
+    /// a different place in the mask. Zero for the other samplers
+    blue_noise: [Vec2<f32>; BLUE_NOISE_DIMENSIONS],
+}
+
+impl Sampler {
+    /// `sample_seed` drives the independent sampler, `seed` is shared by all samples and decides
+    /// the scrambling. `blue_noise` is the mask, only read by the blue noise sampler
+    pub fn new(
+        kind: SamplerKind,
+        pixel: Vec2<u32>,
+        blue_noise: &[f32],
+        index: u32,
+        seed: u32,
+        sample_seed: u32,
+    ) -> Self {
+        let mut shifts = [Vec2::zero(); BLUE_NOISE_DIMENSIONS];
+        if kind == SamplerKind::BlueNoise {
+            for dimension in 0..BLUE_NOISE_DIMENSIONS as u32 {
+                let seed = hash_combine2(seed, dimension);
+                shifts[dimension as usize] = Vec2::new(
+                    blue_noise_at(blue_noise, pixel, hash1(seed)),
+                    blue_noise_at(blue_noise, pixel, hash1(seed ^ 0x5bd1e995)),
+                );
+            }
+        }
+
+        Self {
+            kind,
+            pixel,
+            index,
+            seed,
+            dimension: 0,
+            rand: Rand::from(pixel.with_z(sample_seed)),
+            blue_noise: shifts,
+        }
+    }
+
+    /// Moves to the dimensions of a bounce, the camera ray takes the ones before the first
+    pub fn start_bounce(&mut self, bounce: u32) {
+        self.dimension = CAMERA_DIMENSIONS + bounce * BOUNCE_DIMENSIONS;
+    }
+
+    /// Two numbers from zero to one, each call moves to the next dimension
+    pub fn gen_vec2(&mut self) -> Vec2<f32> {
+        let dimension = self.dimension;
+        self.dimension += 1;
+
+        match self.kind {
+            SamplerKind::Independent => self.rand.gen_vec2(),
+            SamplerKind::Sobol => {
+                let seed = hash_combine2(hash2(self.pixel), hash_combine2(self.seed, dimension));
+                let point = sobol_2d(nested_uniform_scramble(self.index, seed));
+
+                Vec2::new(
+                    to_float(nested_uniform_scramble(point.x, hash1(seed))),
+                    to_float(nested_uniform_scramble(point.y, hash1(seed ^ 0x5bd1e995))),
+                )
+            }
+            SamplerKind::BlueNoise => {
+                // The sequence is shuffled the same way for every pixel. The bounces share the
+                // places in the mask, rotated by a different amount for every dimension
+                let seed = hash_combine2(self.seed, dimension);
+                let point = sobol_2d(nested_uniform_scramble(self.index, seed));
+
+                let place = if dimension < CAMERA_DIMENSIONS {
+                    dimension
+                } else {
+                    CAMERA_DIMENSIONS + (dimension - CAMERA_DIMENSIONS) % BOUNCE_DIMENSIONS
+                };
+                let rotation = Vec2::new(to_float(hash1(seed)), to_float(hash1(seed ^ 0x5bd1e995)));
+                let shift = self.blue_noise[place as usize] + rotation;
+
+                point.map2(shift, |x, shift| fract(to_float(x) + shift))
+            }
+        }
+    }
+
+    /// A number from zero to one, the low discrepancy samplers take a whole dimension
+    pub fn gen_float(&mut self) -> f32 {
+        match self.kind {
+            SamplerKind::Independent => self.rand.gen_float(),
+            _ => self.gen_vec2().x,
+        }
+    }
+
+    /// Whole number below `amount`, zero when there's nothing to pick from
+    pub fn gen_index(&mut self, amount: u32) -> u32 {
+        if amount == 0 {
+            return 0;
+        }
+
+        match self.kind {
+            SamplerKind::Independent => self.rand.gen() % amount,
+            _ => Float::min(self.gen_float() * amount as f32, (amount - 1) as f32) as u32,
+        }
+    }
+
+    /// Uniform on the unit sphere. The low discrepancy samplers map a point instead of rejecting
+    /// them, so every sample uses the same dimensions
+    pub fn gen_unit_vector(&mut self) -> Vec3<f32> {
+        if self.kind == SamplerKind::Independent {
+            return self.rand.gen_unit_vector();
+        }
+
+        let value = self.gen_vec2();
+
+        let z = 1. - 2. * value.x;
+        let radius = Float::sqrt(Float::max(1. - z * z, 0.));
+        let angle = 2. * PI * value.y;
+
+        Vec3::new(radius * Float::cos(angle), radius * Float::sin(angle), z)
+    }
+
+    /// Uniform in the unit disk
+    pub fn gen_in_unit_disk(&mut self) -> Vec2<f32> {
+        if self.kind == SamplerKind::Independent {
+            return self.rand.gen_in_unit_disk();
+        }
+
+        let value = self.gen_vec2();
+
+        let radius = Float::sqrt(value.x);
+        let angle = 2. * PI * value.y;
+
+        Vec2::new(radius * Float::cos(angle), radius * Float::sin(angle))
+    }
+}
@@ -1,26 +1,30 @@
+use core::f32::consts::PI;
+
 use bytemuck::{Pod, Zeroable};
 use spirv_std::num_traits::Float;
-use vek::Vec3;
+use vek::{Vec2, Vec3};
 
 use crate::{
     bvh::Aabb,
-    data::{Face, Range},
+    data::{Face, Range, RayHit},
     material::Material,
     ray::Ray,
-    traits::Raycastable,
-    RayHit,
+    traits::{Bounded, Raycastable},
 };
 
-#[derive(Clone, Copy, Zeroable, Pod)]
+#[derive(Clone, Copy, Default, Zeroable, Pod)]
 #[repr(C)]
 pub struct Sphere {
     pub center: Vec3<f32>,
     pub radius: f32,
     pub material: Material,
+
+    /// Index of the sphere in the scene, kept when the bvh reorders the spheres
+    pub id: u32,
 }
 
-impl Sphere {
-    pub fn get_aabb(self) -> Aabb {
+impl Bounded for Sphere {
+    fn get_aabb(self) -> Aabb {
         let radius = Vec3::broadcast(self.radius);
 
         Aabb::from_extremes(self.center - radius, self.center + radius)
@@ -62,6 +66,11 @@ impl Raycastable for Sphere {
             Face::Back => -outward_normal,
         };
 
+        // Spherical mapping, u goes around the y axis and v from the top down
+        let u = (Float::atan2(-outward_normal.z, outward_normal.x) + PI) / (2. * PI);
+        let v = Float::acos(Float::max(Float::min(outward_normal.y, 1.), -1.)) / PI;
+        let uv = Vec2::new(u, v);
+
         let material = self.material;
 
         RayHit {
@@ -70,7 +79,10 @@ impl Raycastable for Sphere {
             point,
             face,
             normal,
+            uv,
             material,
+            scene_id: self.id,
+            ..Default::default()
         }
     }
 }
@@ -0,0 +1,181 @@
+use bytemuck::{Pod, Zeroable};
+use spirv_std::num_traits::Float;
+use vek::{Vec2, Vec3};
+
+use crate::procedural::{checker, marble, perlin, turbulence, worley};
+
+#[derive(Clone, Copy, Default)]
+#[repr(u32)]
+pub enum TextureKind {
+    /// Texels in the atlas, looked up by uv
+    #[default]
+    Image,
+
+    /// Procedural patterns, evaluated at the hit point
+    Checker,
+    Noise,
+    Marble,
+    Worley,
+}
+
+unsafe impl Zeroable for TextureKind {}
+unsafe impl Pod for TextureKind {}
+
+/// An image in the texture atlas, stored row by row from the top, or a procedural pattern blending
+/// between two colors
+#[derive(Clone, Copy, Default, Zeroable, Pod)]
+#[repr(C)]
+pub struct Texture {
+    pub kind: TextureKind,
+
+    /// Index of the first texel in the atlas
+    pub offset: u32,
+    pub size: Vec2<u32>,
+
+    /// Frequency of procedural patterns, in cells per unit
+    pub scale: f32,
+
+    /// Octaves of turbulence for noise and marble, smooth noise when zero
+    pub octaves: u32,
+
+    pub first_color: Vec3<f32>,
+    pub second_color: Vec3<f32>,
+}
+
+impl Texture {
+    pub fn image(offset: u32, size: Vec2<u32>) -> Self {
+        Self {
+            kind: TextureKind::Image,
+            offset,
+            size,
+            ..Default::default()
+        }
+    }
+
+    /// Alternating cubes, starting with `even` at the origin
+    pub fn checker(scale: f32, even: Vec3<f32>, odd: Vec3<f32>) -> Self {
+        Self {
+            kind: TextureKind::Checker,
+            scale,
+            first_color: even,
+            second_color: odd,
+            ..Default::default()
+        }
+    }
+
+    pub fn noise(scale: f32, octaves: u32, low: Vec3<f32>, high: Vec3<f32>) -> Self {
+        Self {
+            kind: TextureKind::Noise,
+            scale,
+            octaves,
+            first_color: low,
+            second_color: high,
+            ..Default::default()
+        }
+    }
+
+    pub fn marble(scale: f32, octaves: u32, low: Vec3<f32>, high: Vec3<f32>) -> Self {
+        Self {
+            kind: TextureKind::Marble,
+            ..Self::noise(scale, octaves, low, high)
+        }
+    }
+
+    /// Cells colored `near` around their feature point, fading to `far` towards the edges
+    pub fn worley(scale: f32, near: Vec3<f32>, far: Vec3<f32>) -> Self {
+        Self {
+            kind: TextureKind::Worley,
+            scale,
+            first_color: near,
+            second_color: far,
+            ..Default::default()
+        }
+    }
+}
+
+/// Every texture packed one after another into a single buffer of linear colors
+#[derive(Clone, Copy)]
+pub struct Textures<'a> {
+    textures: &'a [Texture],
+    texels: &'a [Vec3<f32>],
+}
+
+/// Wraps a texel coordinate into the texture, repeating it
+fn wrap(coordinate: f32, size: u32) -> u32 {
+    let size = size as f32;
+    let wrapped = coordinate - size * Float::floor(coordinate / size);
+
+    Float::min(wrapped, size - 1.) as u32
+}
+
+impl<'a> Textures<'a> {
+    pub fn new(textures: &'a [Texture], texels: &'a [Vec3<f32>]) -> Self {
+        Self { textures, texels }
+    }
+
+    pub fn size(self, index: u32) -> Vec2<u32> {
+        self.textures[index as usize].size
+    }
+
+    fn texel(self, texture: Texture, x: u32, y: u32) -> Vec3<f32> {
+        self.texels[(texture.offset + y * texture.size.x + x) as usize]
+    }
+
+    /// Color of the texture at a hit point with texture coordinates `uv`
+    pub fn sample(self, index: u32, uv: Vec2<f32>, point: Vec3<f32>) -> Vec3<f32> {
+        let texture = self.textures[index as usize];
+        let point = point * texture.scale;
+
+        let blend = match texture.kind {
+            TextureKind::Image => return self.sample_image(texture, uv),
+            TextureKind::Checker => {
+                if checker(point) {
+                    0.
+                } else {
+                    1.
+                }
+            }
+            TextureKind::Noise => {
+                if texture.octaves == 0 {
+                    0.5 * (1. + perlin(point))
+                } else {
+                    turbulence(point, texture.octaves)
+                }
+            }
+            TextureKind::Marble => marble(point, texture.octaves),
+            TextureKind::Worley => worley(point),
+        };
+
+        Vec3::lerp(texture.first_color, texture.second_color, blend)
+    }
+
+    /// Bilinearly filtered color of an image at `uv`, repeating outside of zero to one
+    fn sample_image(self, texture: Texture, uv: Vec2<f32>) -> Vec3<f32> {
+        // Texel centers lie halfway between whole coordinates
+        let x = uv.x * texture.size.x as f32 - 0.5;
+        let y = uv.y * texture.size.y as f32 - 0.5;
+
+        let floor_x = Float::floor(x);
+        let floor_y = Float::floor(y);
+        let fraction_x = x - floor_x;
+        let fraction_y = y - floor_y;
+
+        let left = wrap(floor_x, texture.size.x);
+        let right = wrap(floor_x + 1., texture.size.x);
+        let top = wrap(floor_y, texture.size.y);
+        let bottom = wrap(floor_y + 1., texture.size.y);
+
+        let upper = Vec3::lerp(
+            self.texel(texture, left, top),
+            self.texel(texture, right, top),
+            fraction_x,
+        );
+        let lower = Vec3::lerp(
+            self.texel(texture, left, bottom),
+            self.texel(texture, right, bottom),
+            fraction_x,
+        );
+
+        Vec3::lerp(upper, lower, fraction_y)
+    }
+}
@@ -1,4 +1,5 @@
 use crate::{
+    bvh::Aabb,
     data::{Range, RayHit},
     ray::Ray,
 };
@@ -6,3 +7,19 @@ use crate::{
 pub trait Raycastable {
     fn raycast(self, ray: Ray, range: Range) -> RayHit;
 }
+
+pub trait Bounded {
+    fn get_aabb(self) -> Aabb;
+}
+
+/// A list of primitives that can be raycast by index, lets a bvh reference primitives that share
+/// data such as triangles indexing into a vertex buffer
+pub trait Primitives: Copy {
+    fn raycast_primitive(self, index: usize, ray: Ray, range: Range) -> RayHit;
+}
+
+impl<T: Raycastable + Copy> Primitives for &[T] {
+    fn raycast_primitive(self, index: usize, ray: Ray, range: Range) -> RayHit {
+        self[index].raycast(ray, range)
+    }
+}
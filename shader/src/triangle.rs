@@ -0,0 +1,151 @@
+use bytemuck::{Pod, Zeroable};
+use spirv_std::num_traits::Float;
+use vek::{Vec2, Vec3};
+
+use crate::{
+    bvh::Aabb,
+    data::{Face, Range, RayHit},
+    material::Material,
+    ray::Ray,
+    traits::Primitives,
+};
+
+#[derive(Clone, Copy, Default, Zeroable, Pod)]
+#[repr(C)]
+pub struct Vertex {
+    pub position: Vec3<f32>,
+
+    /// Zero when the mesh has no normals, the face normal is used instead
+    pub normal: Vec3<f32>,
+    pub uv: Vec2<f32>,
+}
+
+#[derive(Clone, Copy, Default, Zeroable, Pod)]
+#[repr(C)]
+pub struct Triangle {
+    /// Indices into the vertex buffer, counter clockwise
+    pub indices: Vec3<u32>,
+    pub material: Material,
+
+    /// Amount of spheres plus the index of the triangle in the scene, kept when the bvh reorders
+    /// the triangles
+    pub id: u32,
+}
+
+impl Triangle {
+    pub fn get_aabb(self, vertices: &[Vertex]) -> Aabb {
+        let a = vertices[self.indices.x as usize].position;
+        let b = vertices[self.indices.y as usize].position;
+        let c = vertices[self.indices.z as usize].position;
+
+        let bounds = Aabb::combine(Aabb::from_extremes(a, b), Aabb::from_extremes(c, c));
+
+        // Pad the bounds so axis aligned triangles don't produce flat boxes
+        let padding = 1e-4;
+
+        Aabb {
+            axes: Vec3::new(
+                bounds.axes.x.expand(padding),
+                bounds.axes.y.expand(padding),
+                bounds.axes.z.expand(padding),
+            ),
+        }
+    }
+
+    /// Möller–Trumbore intersection. The range checks are spelled out, `RangeInclusive::contains`
+    /// compares through references that don't compile to spirv
+    #[allow(clippy::manual_range_contains)]
+    pub fn raycast(self, vertices: &[Vertex], ray: Ray, range: Range) -> RayHit {
+        let a = vertices[self.indices.x as usize];
+        let b = vertices[self.indices.y as usize];
+        let c = vertices[self.indices.z as usize];
+
+        let edge_ab = b.position - a.position;
+        let edge_ac = c.position - a.position;
+
+        let p = Vec3::cross(ray.direction, edge_ac);
+        let determinant = Vec3::dot(edge_ab, p);
+
+        // Ray is parallel to the triangle
+        if Float::abs(determinant) < 1e-8 {
+            return RayHit::none();
+        }
+
+        let inverse_determinant = 1. / determinant;
+        let a_to_origin = ray.origin - a.position;
+
+        let u = Vec3::dot(a_to_origin, p) * inverse_determinant;
+        if u < 0. || u > 1. {
+            return RayHit::none();
+        }
+
+        let q = Vec3::cross(a_to_origin, edge_ab);
+        let v = Vec3::dot(ray.direction, q) * inverse_determinant;
+        if v < 0. || u + v > 1. {
+            return RayHit::none();
+        }
+
+        let distance = Vec3::dot(edge_ac, q) * inverse_determinant;
+        if !range.contains(distance) {
+            return RayHit::none();
+        }
+
+        let w = 1. - u - v;
+        let point = ray.at(distance);
+
+        let face_normal = Vec3::cross(edge_ab, edge_ac).normalized();
+        let face = ray.get_face(face_normal);
+
+        let interpolated_normal = w * a.normal + u * b.normal + v * c.normal;
+        let outward_normal = if interpolated_normal.magnitude_squared() < 1e-8 {
+            face_normal
+        } else if Vec3::dot(interpolated_normal, face_normal) < 0. {
+            // Vertex normals disagree with the winding order
+            -interpolated_normal.normalized()
+        } else {
+            interpolated_normal.normalized()
+        };
+
+        let normal = match face {
+            Face::Front => outward_normal,
+            Face::Back => -outward_normal,
+        };
+
+        let uv = w * a.uv + u * b.uv + v * c.uv;
+        let material = self.material;
+
+        RayHit {
+            did_hit: true,
+            distance,
+            point,
+            face,
+            normal,
+            uv,
+            material,
+            scene_id: self.id,
+            ..Default::default()
+        }
+    }
+}
+
+/// Triangles together with the vertex buffer they index into
+#[derive(Clone, Copy)]
+pub struct Mesh<'a> {
+    vertices: &'a [Vertex],
+    triangles: &'a [Triangle],
+}
+
+impl<'a> Mesh<'a> {
+    pub fn new(vertices: &'a [Vertex], triangles: &'a [Triangle]) -> Self {
+        Self {
+            vertices,
+            triangles,
+        }
+    }
+}
+
+impl<'a> Primitives for Mesh<'a> {
+    fn raycast_primitive(self, index: usize, ray: Ray, range: Range) -> RayHit {
+        self.triangles[index].raycast(self.vertices, ray, range)
+    }
+}
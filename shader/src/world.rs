@@ -0,0 +1,41 @@
+use crate::{
+    bvh::Bvh,
+    data::{Range, RayHit},
+    ray::Ray,
+    sphere::Sphere,
+    traits::Raycastable,
+    triangle::Mesh,
+};
+
+/// Every primitive in the scene, each kind with its own bvh
+#[derive(Clone, Copy)]
+pub struct World<'a> {
+    pub spheres: Bvh<'a, &'a [Sphere]>,
+    pub mesh: Bvh<'a, Mesh<'a>>,
+}
+
+impl<'a> Raycastable for World<'a> {
+    fn raycast(self, ray: Ray, range: Range) -> RayHit {
+        let sphere_hit = self.spheres.raycast(ray, range);
+
+        let closest_distance = if sphere_hit.did_hit {
+            sphere_hit.distance
+        } else {
+            range.max
+        };
+
+        let mesh_hit = self
+            .mesh
+            .raycast(ray, Range::new(range.min, closest_distance));
+
+        if mesh_hit.did_hit {
+            // Triangles are numbered after the spheres
+            RayHit {
+                object_id: mesh_hit.object_id + self.spheres.amount(),
+                ..mesh_hit
+            }
+        } else {
+            sphere_hit
+        }
+    }
+}